@@ -0,0 +1,156 @@
+use super::{
+    AudioBackend, BackendError, DataCallback, DeviceId, ErrorCallback, InputBuffer,
+    InputDeviceInfo, InputStream,
+};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+/// Backend that talks to real hardware through the default cpal host.
+pub struct CpalBackend {
+    host: cpal::Host,
+}
+
+impl CpalBackend {
+    pub fn new(host: cpal::Host) -> Self {
+        Self { host }
+    }
+
+    /// Input devices with their IDs. Devices whose name can't be read are
+    /// skipped, since they could not be told apart anyway.
+    fn devices(&self) -> Result<Vec<(DeviceId, cpal::Device)>, BackendError> {
+        let devices: Vec<(String, cpal::Device)> = self
+            .host
+            .input_devices()
+            .map_err(|e| BackendError::Host(e.to_string()))?
+            .filter_map(|d| Some((d.name().ok()?, d)))
+            .collect();
+        let ids = DeviceId::assign(
+            self.host.id().name(),
+            devices.iter().map(|(name, _)| name.clone()),
+        );
+        Ok(ids
+            .into_iter()
+            .zip(devices.into_iter().map(|(_, device)| device))
+            .collect())
+    }
+
+    fn find_device(&self, id: &DeviceId) -> Result<cpal::Device, BackendError> {
+        self.devices()?
+            .into_iter()
+            .find(|(device_id, _)| device_id == id)
+            .map(|(_, device)| device)
+            .ok_or(BackendError::DeviceNotFound)
+    }
+}
+
+impl Default for CpalBackend {
+    fn default() -> Self {
+        Self::new(cpal::default_host())
+    }
+}
+
+impl AudioBackend for CpalBackend {
+    fn input_devices(&self) -> Result<Vec<InputDeviceInfo>, BackendError> {
+        // cpal only exposes the default device's name, so devices sharing
+        // that name are all reported as the default.
+        let default_name = self.host.default_input_device().and_then(|d| d.name().ok());
+        Ok(self
+            .devices()?
+            .into_iter()
+            .map(|(id, d)| InputDeviceInfo {
+                is_default: default_name.as_deref() == Some(id.name.as_str()),
+                name: id.name.clone(),
+                default_config: d.default_input_config().ok(),
+                supported_configs: d
+                    .supported_input_configs()
+                    .map(|configs| configs.collect())
+                    .unwrap_or_default(),
+                id,
+            })
+            .collect())
+    }
+
+    fn input_device_ids(&self) -> Result<Vec<DeviceId>, BackendError> {
+        Ok(self.devices()?.into_iter().map(|(id, _)| id).collect())
+    }
+
+    fn default_input_config(
+        &self,
+        device: &DeviceId,
+    ) -> Result<cpal::SupportedStreamConfig, BackendError> {
+        self.find_device(device)?
+            .default_input_config()
+            .map_err(|e| BackendError::DefaultConfig(e.to_string()))
+    }
+
+    fn supported_input_configs(
+        &self,
+        device: &DeviceId,
+    ) -> Result<Vec<cpal::SupportedStreamConfigRange>, BackendError> {
+        self.find_device(device)?
+            .supported_input_configs()
+            .map(|configs| configs.collect())
+            .map_err(|e| BackendError::SupportedConfigs(e.to_string()))
+    }
+
+    fn build_input_stream(
+        &self,
+        device: &DeviceId,
+        config: &cpal::SupportedStreamConfig,
+        buffer_size: cpal::BufferSize,
+        mut on_data: DataCallback,
+        on_error: ErrorCallback,
+    ) -> Result<Box<dyn InputStream>, BackendError> {
+        let device = self.find_device(device)?;
+        let stream_config = cpal::StreamConfig {
+            buffer_size,
+            ..config.config()
+        };
+
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::I8 => device.build_input_stream(
+                &stream_config,
+                move |data: &[i8], _: &_| on_data(InputBuffer::I8(data)),
+                on_error,
+                None,
+            ),
+            cpal::SampleFormat::I16 => device.build_input_stream(
+                &stream_config,
+                move |data: &[i16], _: &_| on_data(InputBuffer::I16(data)),
+                on_error,
+                None,
+            ),
+            cpal::SampleFormat::I32 => device.build_input_stream(
+                &stream_config,
+                move |data: &[i32], _: &_| on_data(InputBuffer::I32(data)),
+                on_error,
+                None,
+            ),
+            cpal::SampleFormat::F32 => device.build_input_stream(
+                &stream_config,
+                move |data: &[f32], _: &_| on_data(InputBuffer::F32(data)),
+                on_error,
+                None,
+            ),
+            other => return Err(BackendError::UnsupportedSampleFormat(other)),
+        }
+        .map_err(|e| BackendError::BuildStream(e.to_string()))?;
+
+        Ok(Box::new(CpalStream(stream)))
+    }
+}
+
+struct CpalStream(cpal::Stream);
+
+impl InputStream for CpalStream {
+    fn play(&self) -> Result<(), BackendError> {
+        self.0
+            .play()
+            .map_err(|e| BackendError::PlayStream(e.to_string()))
+    }
+
+    fn pause(&self) -> Result<(), BackendError> {
+        self.0
+            .pause()
+            .map_err(|e| BackendError::PauseStream(e.to_string()))
+    }
+}
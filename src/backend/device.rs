@@ -0,0 +1,153 @@
+use super::BackendError;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Identifies an input device across runs, written `<host>:<name>#<ordinal>`.
+/// The ordinal tells apart devices that share a display name (e.g. two
+/// identical USB microphones) and is the device's position among them in
+/// host enumeration order, starting at 0.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DeviceId {
+    pub host: String,
+    pub name: String,
+    pub ordinal: usize,
+}
+
+impl DeviceId {
+    /// IDs for the devices of `host`, given their names in enumeration order.
+    pub fn assign(host: &str, names: impl IntoIterator<Item = String>) -> Vec<DeviceId> {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        names
+            .into_iter()
+            .map(|name| {
+                let count = seen.entry(name.clone()).or_default();
+                let ordinal = *count;
+                *count += 1;
+                DeviceId {
+                    host: host.to_string(),
+                    name,
+                    ordinal,
+                }
+            })
+            .collect()
+    }
+
+    /// Picks the device `selector` refers to. A selector is either a full
+    /// device ID or a display name; a name shared by several devices is
+    /// rejected rather than silently resolved to the first of them.
+    pub fn resolve(ids: &[DeviceId], selector: &str) -> Result<DeviceId, BackendError> {
+        if let Ok(id) = selector.parse::<DeviceId>() {
+            if ids.contains(&id) {
+                return Ok(id);
+            }
+        }
+        let matches: Vec<&DeviceId> = ids.iter().filter(|id| id.name == selector).collect();
+        match matches.as_slice() {
+            [] => Err(BackendError::DeviceNotFound),
+            [id] => Ok((*id).clone()),
+            _ => Err(BackendError::AmbiguousDevice(
+                matches
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}#{}", self.host, self.name, self.ordinal)
+    }
+}
+
+impl FromStr for DeviceId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid device ID: {} (expected <host>:<name>#<n>)", s);
+        let (host, rest) = s.split_once(':').ok_or_else(invalid)?;
+        let (name, ordinal) = rest.rsplit_once('#').ok_or_else(invalid)?;
+        Ok(DeviceId {
+            host: host.to_string(),
+            name: name.to_string(),
+            ordinal: ordinal.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids() -> Vec<DeviceId> {
+        DeviceId::assign(
+            "ALSA",
+            ["default", "USB Mic", "hw:CARD=PCH,DEV=0", "USB Mic"].map(String::from),
+        )
+    }
+
+    #[test]
+    fn assigns_ordinals_per_name() {
+        let ordinals: Vec<usize> = ids().iter().map(|id| id.ordinal).collect();
+        assert_eq!(ordinals, [0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn display_and_parse_round_trip() {
+        for id in ids() {
+            assert_eq!(id.to_string().parse::<DeviceId>(), Ok(id));
+        }
+        assert_eq!(
+            "WASAPI:Mic #2 (Realtek)#1".parse::<DeviceId>(),
+            Ok(DeviceId {
+                host: "WASAPI".to_string(),
+                name: "Mic #2 (Realtek)".to_string(),
+                ordinal: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_ids() {
+        for selector in [
+            "",
+            "USB Mic",
+            "ALSA:USB Mic",
+            "ALSA:USB Mic#",
+            "ALSA:USB Mic#x",
+        ] {
+            assert!(selector.parse::<DeviceId>().is_err(), "{}", selector);
+        }
+    }
+
+    #[test]
+    fn resolves_ids_and_unique_names() {
+        let ids = ids();
+        assert_eq!(DeviceId::resolve(&ids, "ALSA:USB Mic#1").unwrap(), ids[3]);
+        assert_eq!(DeviceId::resolve(&ids, "default").unwrap(), ids[0]);
+        assert_eq!(
+            DeviceId::resolve(&ids, "hw:CARD=PCH,DEV=0").unwrap(),
+            ids[2]
+        );
+    }
+
+    #[test]
+    fn rejects_ambiguous_and_unknown_selectors() {
+        let ids = ids();
+        match DeviceId::resolve(&ids, "USB Mic") {
+            Err(BackendError::AmbiguousDevice(choices)) => {
+                assert_eq!(choices, "ALSA:USB Mic#0, ALSA:USB Mic#1")
+            }
+            other => panic!("expected an ambiguous device error, got {:?}", other),
+        }
+        for selector in ["Headset", "ALSA:USB Mic#2", "CoreAudio:USB Mic#0"] {
+            assert!(matches!(
+                DeviceId::resolve(&ids, selector),
+                Err(BackendError::DeviceNotFound)
+            ));
+        }
+    }
+}
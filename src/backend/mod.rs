@@ -0,0 +1,112 @@
+mod cpal_backend;
+mod device;
+mod synthetic;
+
+pub use cpal_backend::CpalBackend;
+pub use device::DeviceId;
+pub use synthetic::{Signal, SyntheticBackend, SyntheticDevice, SyntheticFaults};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum BackendError {
+    #[error("Device not found")]
+    DeviceNotFound,
+    #[error("Several devices have this name, use one of these device IDs instead: {0}")]
+    AmbiguousDevice(String),
+    #[error("Host error: {0}")]
+    Host(String),
+    #[error("Failed to get default input config: {0}")]
+    DefaultConfig(String),
+    #[error("Failed to get supported input configs: {0}")]
+    SupportedConfigs(String),
+    #[error("Unsupported sample format: {0:?}")]
+    UnsupportedSampleFormat(cpal::SampleFormat),
+    #[error("Failed to build stream: {0}")]
+    BuildStream(String),
+    #[error("Failed to start stream: {0}")]
+    PlayStream(String),
+    #[error("Failed to pause stream: {0}")]
+    PauseStream(String),
+}
+
+/// An input device and the configs it can open with.
+#[derive(Debug, Clone)]
+pub struct InputDeviceInfo {
+    pub id: DeviceId,
+    pub name: String,
+    /// Whether this is the host's default input device.
+    pub is_default: bool,
+    /// The config a session uses for this device. `None` if the host could
+    /// not report one, e.g. because the device is busy.
+    pub default_config: Option<cpal::SupportedStreamConfig>,
+    /// Every channel count, sample rate range, sample format and buffer
+    /// size range the device accepts.
+    pub supported_configs: Vec<cpal::SupportedStreamConfigRange>,
+}
+
+/// A block of interleaved samples in the device's native sample format.
+#[derive(Debug, Clone, Copy)]
+pub enum InputBuffer<'a> {
+    I8(&'a [i8]),
+    I16(&'a [i16]),
+    I32(&'a [i32]),
+    F32(&'a [f32]),
+}
+
+impl InputBuffer<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            InputBuffer::I8(data) => data.len(),
+            InputBuffer::I16(data) => data.len(),
+            InputBuffer::I32(data) => data.len(),
+            InputBuffer::F32(data) => data.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub type DataCallback = Box<dyn FnMut(InputBuffer<'_>) + Send + 'static>;
+pub type ErrorCallback = Box<dyn FnMut(cpal::StreamError) + Send + 'static>;
+
+/// A running input stream. Dropping it stops the stream and releases the device.
+pub trait InputStream {
+    fn play(&self) -> Result<(), BackendError>;
+    fn pause(&self) -> Result<(), BackendError>;
+}
+
+/// Everything the audio thread needs from the audio host: device enumeration,
+/// config discovery and opening input streams.
+pub trait AudioBackend {
+    fn input_devices(&self) -> Result<Vec<InputDeviceInfo>, BackendError>;
+
+    /// IDs of the input devices, without querying their configs.
+    fn input_device_ids(&self) -> Result<Vec<DeviceId>, BackendError>;
+
+    /// Turns a device ID or a unique display name into a device ID.
+    fn resolve_device(&self, selector: &str) -> Result<DeviceId, BackendError> {
+        DeviceId::resolve(&self.input_device_ids()?, selector)
+    }
+
+    fn default_input_config(
+        &self,
+        device: &DeviceId,
+    ) -> Result<cpal::SupportedStreamConfig, BackendError>;
+
+    fn supported_input_configs(
+        &self,
+        device: &DeviceId,
+    ) -> Result<Vec<cpal::SupportedStreamConfigRange>, BackendError>;
+
+    fn build_input_stream(
+        &self,
+        device: &DeviceId,
+        config: &cpal::SupportedStreamConfig,
+        buffer_size: cpal::BufferSize,
+        on_data: DataCallback,
+        on_error: ErrorCallback,
+    ) -> Result<Box<dyn InputStream>, BackendError>;
+}
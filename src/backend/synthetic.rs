@@ -0,0 +1,463 @@
+use super::{
+    AudioBackend, BackendError, DataCallback, DeviceId, ErrorCallback, InputBuffer,
+    InputDeviceInfo, InputStream,
+};
+use cpal::Sample;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// What a synthetic device "hears".
+#[derive(Debug, Clone)]
+pub enum Signal {
+    Sine {
+        frequency: f32,
+        amplitude: f32,
+    },
+    Noise {
+        amplitude: f32,
+    },
+    Silence,
+    /// Interleaved samples played back once, followed by silence.
+    Scripted(Arc<[f32]>),
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntheticDevice {
+    pub name: String,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub sample_format: cpal::SampleFormat,
+    pub signal: Signal,
+    /// Configs the device accepts besides its default one, which is always
+    /// supported. A stream may be opened with any config in these ranges.
+    pub supported_configs: Vec<cpal::SupportedStreamConfigRange>,
+}
+
+impl SyntheticDevice {
+    /// A mono 48 kHz f32 device producing `signal`.
+    pub fn new(name: impl Into<String>, signal: Signal) -> Self {
+        Self {
+            name: name.into(),
+            channels: 1,
+            sample_rate: 48_000,
+            sample_format: cpal::SampleFormat::F32,
+            signal,
+            supported_configs: Vec::new(),
+        }
+    }
+}
+
+/// A fault waiting to be delivered to a running stream.
+enum Fault {
+    Unplugged,
+    Error(String),
+}
+
+#[derive(Default)]
+struct FaultState {
+    /// Names of devices that are currently unplugged.
+    unplugged: HashSet<String>,
+    /// Device name and fault inbox of every stream built so far.
+    streams: Vec<(String, Weak<Mutex<Vec<Fault>>>)>,
+}
+
+/// Injects stream errors and device loss into a `SyntheticBackend`, so error
+/// handling can be exercised without hardware. Clones share the backend's
+/// state, so a handle taken before the backend is handed to a `Recorder`
+/// keeps working. Devices are picked by name; every device with that name
+/// is affected.
+#[derive(Clone, Default)]
+pub struct SyntheticFaults {
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl SyntheticFaults {
+    /// Removes the device: it disappears from enumeration, can't be opened,
+    /// and its running streams stop delivering audio and report
+    /// `StreamError::DeviceNotAvailable` once.
+    pub fn unplug(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.unplugged.insert(name.to_string());
+        Self::send(&mut state, name, || Fault::Unplugged);
+    }
+
+    /// Makes an unplugged device available again. Streams that saw it
+    /// disappear stay dead; it has to be opened anew.
+    pub fn replug(&self, name: &str) {
+        self.state.lock().unwrap().unplugged.remove(name);
+    }
+
+    /// Reports `StreamError::BackendSpecific` with `description` on the
+    /// device's running streams, which keep going.
+    pub fn stream_error(&self, name: &str, description: &str) {
+        let mut state = self.state.lock().unwrap();
+        Self::send(&mut state, name, || Fault::Error(description.to_string()));
+    }
+
+    fn is_unplugged(&self, name: &str) -> bool {
+        self.state.lock().unwrap().unplugged.contains(name)
+    }
+
+    fn register(&self, name: &str) -> Arc<Mutex<Vec<Fault>>> {
+        let inbox = Arc::new(Mutex::new(Vec::new()));
+        self.state
+            .lock()
+            .unwrap()
+            .streams
+            .push((name.to_string(), Arc::downgrade(&inbox)));
+        inbox
+    }
+
+    fn send(state: &mut FaultState, name: &str, fault: impl Fn() -> Fault) {
+        state
+            .streams
+            .retain(|(device, inbox)| match inbox.upgrade() {
+                Some(inbox) => {
+                    if device == name {
+                        inbox.lock().unwrap().push(fault());
+                    }
+                    true
+                }
+                None => false,
+            });
+    }
+}
+
+/// Backend that generates audio in software, paced in real time, so the audio
+/// thread can run on machines without a sound card.
+pub struct SyntheticBackend {
+    devices: Vec<SyntheticDevice>,
+    block_frames: usize,
+    faults: SyntheticFaults,
+}
+
+impl SyntheticBackend {
+    pub fn new(devices: Vec<SyntheticDevice>) -> Self {
+        Self {
+            devices,
+            block_frames: 480,
+            faults: SyntheticFaults::default(),
+        }
+    }
+
+    /// Handle for unplugging devices and injecting stream errors.
+    pub fn faults(&self) -> SyntheticFaults {
+        self.faults.clone()
+    }
+
+    /// Number of frames delivered per callback (480 by default, 10 ms at 48 kHz).
+    pub fn with_block_frames(mut self, block_frames: usize) -> Self {
+        self.block_frames = block_frames.max(1);
+        self
+    }
+
+    /// Devices that are not unplugged, in enumeration order.
+    fn present(&self) -> Vec<&SyntheticDevice> {
+        self.devices
+            .iter()
+            .filter(|d| !self.faults.is_unplugged(&d.name))
+            .collect()
+    }
+
+    fn ids(&self) -> Vec<DeviceId> {
+        DeviceId::assign("Synthetic", self.present().iter().map(|d| d.name.clone()))
+    }
+
+    fn find_device(&self, id: &DeviceId) -> Result<&SyntheticDevice, BackendError> {
+        self.ids()
+            .iter()
+            .zip(self.present())
+            .find(|(device_id, _)| *device_id == id)
+            .map(|(_, device)| device)
+            .ok_or(BackendError::DeviceNotFound)
+    }
+
+    fn config(&self, device: &SyntheticDevice) -> cpal::SupportedStreamConfig {
+        cpal::SupportedStreamConfig::new(
+            device.channels,
+            cpal::SampleRate(device.sample_rate),
+            cpal::SupportedBufferSize::Range {
+                min: self.block_frames as u32,
+                max: self.block_frames as u32,
+            },
+            device.sample_format,
+        )
+    }
+
+    /// The device's default config, followed by the ranges it declares.
+    fn supported_ranges(&self, device: &SyntheticDevice) -> Vec<cpal::SupportedStreamConfigRange> {
+        let config = self.config(device);
+        let default = cpal::SupportedStreamConfigRange::new(
+            config.channels(),
+            config.sample_rate(),
+            config.sample_rate(),
+            *config.buffer_size(),
+            config.sample_format(),
+        );
+        std::iter::once(default)
+            .chain(device.supported_configs.iter().cloned())
+            .collect()
+    }
+}
+
+impl Default for SyntheticBackend {
+    fn default() -> Self {
+        Self::new(vec![
+            SyntheticDevice {
+                channels: 2,
+                ..SyntheticDevice::new(
+                    "default",
+                    Signal::Sine {
+                        frequency: 440.0,
+                        amplitude: 0.5,
+                    },
+                )
+            },
+            SyntheticDevice {
+                sample_rate: 44_100,
+                sample_format: cpal::SampleFormat::I16,
+                ..SyntheticDevice::new("Synthetic Noise", Signal::Noise { amplitude: 0.25 })
+            },
+            SyntheticDevice::new("Synthetic Silence", Signal::Silence),
+        ])
+    }
+}
+
+impl AudioBackend for SyntheticBackend {
+    /// The first device that is plugged in is reported as the default.
+    fn input_devices(&self) -> Result<Vec<InputDeviceInfo>, BackendError> {
+        Ok(self
+            .ids()
+            .into_iter()
+            .zip(self.present())
+            .enumerate()
+            .map(|(index, (id, device))| InputDeviceInfo {
+                id,
+                name: device.name.clone(),
+                is_default: index == 0,
+                default_config: Some(self.config(device)),
+                supported_configs: self.supported_ranges(device),
+            })
+            .collect())
+    }
+
+    fn input_device_ids(&self) -> Result<Vec<DeviceId>, BackendError> {
+        Ok(self.ids())
+    }
+
+    fn default_input_config(
+        &self,
+        device: &DeviceId,
+    ) -> Result<cpal::SupportedStreamConfig, BackendError> {
+        Ok(self.config(self.find_device(device)?))
+    }
+
+    fn supported_input_configs(
+        &self,
+        device: &DeviceId,
+    ) -> Result<Vec<cpal::SupportedStreamConfigRange>, BackendError> {
+        Ok(self.supported_ranges(self.find_device(device)?))
+    }
+
+    fn build_input_stream(
+        &self,
+        device: &DeviceId,
+        config: &cpal::SupportedStreamConfig,
+        buffer_size: cpal::BufferSize,
+        mut on_data: DataCallback,
+        mut on_error: ErrorCallback,
+    ) -> Result<Box<dyn InputStream>, BackendError> {
+        let device = self.find_device(device)?;
+        let supported = self.supported_ranges(device).iter().any(|range| {
+            range.channels() == config.channels()
+                && range.sample_format() == config.sample_format()
+                && (range.min_sample_rate()..=range.max_sample_rate())
+                    .contains(&config.sample_rate())
+        });
+        if !supported {
+            return Err(BackendError::BuildStream(format!(
+                "Unsupported stream config: {:?}",
+                config
+            )));
+        }
+        let sample_format = config.sample_format();
+        if !matches!(
+            sample_format,
+            cpal::SampleFormat::I8
+                | cpal::SampleFormat::I16
+                | cpal::SampleFormat::I32
+                | cpal::SampleFormat::F32
+        ) {
+            return Err(BackendError::UnsupportedSampleFormat(sample_format));
+        }
+
+        let mut generator = Generator::new(
+            device.signal.clone(),
+            config.channels() as usize,
+            config.sample_rate().0,
+        );
+        let block_frames = match buffer_size {
+            cpal::BufferSize::Fixed(frames) => frames.max(1) as usize,
+            cpal::BufferSize::Default => self.block_frames,
+        };
+        let block_duration =
+            Duration::from_secs_f64(block_frames as f64 / config.sample_rate().0 as f64);
+
+        let playing = Arc::new(AtomicBool::new(false));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let playing_clone = Arc::clone(&playing);
+        let shutdown_clone = Arc::clone(&shutdown);
+        let faults = self.faults.register(&device.name);
+
+        let handle = std::thread::spawn(move || {
+            let mut next_tick = Instant::now();
+            let mut unplugged = false;
+            while !shutdown_clone.load(Ordering::Acquire) {
+                // Faults are reported from the stream's thread, like a real
+                // host does.
+                let pending = std::mem::take(&mut *faults.lock().unwrap());
+                for fault in pending {
+                    on_error(match fault {
+                        Fault::Unplugged if unplugged => continue,
+                        Fault::Unplugged => {
+                            unplugged = true;
+                            cpal::StreamError::DeviceNotAvailable
+                        }
+                        Fault::Error(description) => cpal::StreamError::BackendSpecific {
+                            err: cpal::BackendSpecificError { description },
+                        },
+                    });
+                }
+
+                if playing_clone.load(Ordering::Acquire) && !unplugged {
+                    let block = generator.next_block(block_frames);
+                    deliver(&block, sample_format, &mut on_data);
+                    next_tick += block_duration;
+                } else {
+                    next_tick = Instant::now() + block_duration;
+                }
+
+                let now = Instant::now();
+                if next_tick > now {
+                    std::thread::sleep(next_tick - now);
+                }
+            }
+        });
+
+        Ok(Box::new(SyntheticStream {
+            playing,
+            shutdown,
+            handle: Some(handle),
+        }))
+    }
+}
+
+fn deliver(block: &[f32], sample_format: cpal::SampleFormat, on_data: &mut DataCallback) {
+    match sample_format {
+        cpal::SampleFormat::I8 => {
+            let data: Vec<i8> = block.iter().map(|&s| i8::from_sample(s)).collect();
+            on_data(InputBuffer::I8(&data));
+        }
+        cpal::SampleFormat::I16 => {
+            let data: Vec<i16> = block.iter().map(|&s| i16::from_sample(s)).collect();
+            on_data(InputBuffer::I16(&data));
+        }
+        cpal::SampleFormat::I32 => {
+            let data: Vec<i32> = block.iter().map(|&s| i32::from_sample(s)).collect();
+            on_data(InputBuffer::I32(&data));
+        }
+        _ => on_data(InputBuffer::F32(block)),
+    }
+}
+
+struct Generator {
+    signal: Signal,
+    channels: usize,
+    sample_rate: u32,
+    frame_index: u64,
+    noise_state: u32,
+}
+
+impl Generator {
+    fn new(signal: Signal, channels: usize, sample_rate: u32) -> Self {
+        Self {
+            signal,
+            channels: channels.max(1),
+            sample_rate,
+            frame_index: 0,
+            noise_state: 0x9E37_79B9,
+        }
+    }
+
+    fn next_block(&mut self, frames: usize) -> Vec<f32> {
+        let mut block = Vec::with_capacity(frames * self.channels);
+        for _ in 0..frames {
+            match &self.signal {
+                Signal::Sine {
+                    frequency,
+                    amplitude,
+                } => {
+                    let t = self.frame_index as f64 / self.sample_rate as f64;
+                    let value = (t * *frequency as f64 * std::f64::consts::TAU).sin() as f32;
+                    block.extend(std::iter::repeat_n(value * amplitude, self.channels));
+                }
+                Signal::Noise { amplitude } => {
+                    let amplitude = *amplitude;
+                    for _ in 0..self.channels {
+                        let value = self.next_noise() * amplitude;
+                        block.push(value);
+                    }
+                }
+                Signal::Silence => block.extend(std::iter::repeat_n(0.0, self.channels)),
+                Signal::Scripted(samples) => {
+                    let start = self.frame_index as usize * self.channels;
+                    for channel in 0..self.channels {
+                        block.push(samples.get(start + channel).copied().unwrap_or(0.0));
+                    }
+                }
+            }
+            self.frame_index += 1;
+        }
+        block
+    }
+
+    /// Uniform white noise in [-1, 1] from a xorshift32 generator.
+    fn next_noise(&mut self) -> f32 {
+        let mut x = self.noise_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.noise_state = x;
+        (x as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+struct SyntheticStream {
+    playing: Arc<AtomicBool>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl InputStream for SyntheticStream {
+    fn play(&self) -> Result<(), BackendError> {
+        self.playing.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), BackendError> {
+        self.playing.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Drop for SyntheticStream {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,644 @@
+use crate::backend::InputBuffer;
+use crate::channels::ChannelPolicy;
+use crate::chunks::ChunkSubscribers;
+use crate::encoder::{Encoder, OutputFormat};
+use crate::events::{AutoStopReason, EventBus, RecorderEvent};
+use crate::health::{InputHealthConfig, InputMonitor, InputStats};
+use crate::meter::{Meter, MeterReading};
+use crate::preroll::PreRoll;
+use crate::resample::{ResampleQuality, Resampler};
+use crate::sample;
+use crate::segment::{CompletedSegment, SegmentLimit, Segmenter};
+use crate::sink::{MemoryBuffer, RecordingOutput, RecordingTarget};
+use crate::thread::{RecordingOptions, RecordingState, UserRecordingSessionConfig};
+use crate::vad::{self, VadConfig, VoiceActivityDetector};
+
+/// A file being encoded, plus the per-recording DSP state that feeds it.
+pub(crate) struct ActiveRecording {
+    output: RecordingOutput,
+    writer: Encoder,
+    memory: Option<MemoryBuffer>,
+    resampler: Option<Resampler>,
+    resampled: Vec<f32>,
+    frames_written: u64,
+    /// Output frames after which nothing more is written.
+    max_frames: Option<u64>,
+    segments: Option<Segmenter>,
+    monitor: InputMonitor,
+}
+
+impl ActiveRecording {
+    pub(crate) fn output(&self) -> &RecordingOutput {
+        &self.output
+    }
+
+    /// The file currently being written, if any. For segmented recordings
+    /// this is the segment in progress.
+    pub(crate) fn current_file(&self) -> Option<String> {
+        match (&self.output, &self.segments) {
+            (_, Some(segments)) => Some(segments.current_path()),
+            (RecordingOutput::File(filename), None) => Some(filename.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn format(&self) -> OutputFormat {
+        self.writer.format()
+    }
+
+    pub(crate) fn input_stats(&self) -> InputStats {
+        self.monitor.stats()
+    }
+
+    /// Output frames written so far, after resampling.
+    pub(crate) fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+
+    pub(crate) fn limit_reached(&self) -> bool {
+        self.max_frames
+            .is_some_and(|max| self.frames_written >= max)
+    }
+
+    fn write(&mut self, samples: &[f32]) -> hound::Result<()> {
+        if self.resampler.is_none() {
+            return self.write_output(samples);
+        }
+        let mut resampled = std::mem::take(&mut self.resampled);
+        resampled.clear();
+        if let Some(resampler) = &mut self.resampler {
+            resampler.process(samples, &mut resampled);
+        }
+        let result = self.write_output(&resampled);
+        self.resampled = resampled;
+        result
+    }
+
+    /// Writes resampled frames, cutting off exactly at `max_frames` and
+    /// rotating to the next segment file exactly at the segment length.
+    fn write_output(&mut self, samples: &[f32]) -> hound::Result<()> {
+        let channels = self.writer.channels() as usize;
+        let mut samples = match self.max_frames {
+            Some(max) => {
+                let remaining = max.saturating_sub(self.frames_written) as usize;
+                &samples[..samples.len().min(remaining * channels)]
+            }
+            None => samples,
+        };
+
+        while !samples.is_empty() {
+            let mut frames = samples.len() / channels;
+            if let Some(segments) = &self.segments {
+                // Rotate lazily, so the last segment is never left empty.
+                if segments.remaining() == 0 {
+                    self.rotate()?;
+                }
+            }
+            if let Some(segments) = &mut self.segments {
+                frames = frames.min(segments.remaining() as usize);
+                segments.record(frames as u64);
+            }
+            let (now, rest) = samples.split_at(frames * channels);
+            self.writer.write(now)?;
+            self.frames_written += frames as u64;
+            samples = rest;
+        }
+        Ok(())
+    }
+
+    /// Swaps in a writer for the next segment and finalizes the full one.
+    /// Both happen within one `write_output` call, under the capture lock,
+    /// so no frame is lost or written twice.
+    fn rotate(&mut self) -> hound::Result<()> {
+        let Some(segments) = &mut self.segments else {
+            return Ok(());
+        };
+        let (sink, _) = RecordingTarget::File(segments.next_path()).open()?;
+        let next = self.writer.reopen(sink)?;
+        std::mem::replace(&mut self.writer, next).finalize()?;
+        segments.advance();
+        Ok(())
+    }
+
+    /// Finalizes the recording, keeping what the caller needs to report it.
+    pub(crate) fn finish(mut self) -> FinishedRecording {
+        let output = self.output.clone();
+        let format = self.format();
+        let input_stats = self.input_stats();
+        let result = self.flush_resampler();
+        let mut segments = self.segments.take();
+        let result = result.and_then(|()| self.finalize());
+        if let (Ok(_), Some(segments)) = (&result, &mut segments) {
+            segments.complete();
+        }
+        FinishedRecording {
+            output,
+            format,
+            input_stats,
+            result,
+            segments: segments.map_or_else(Vec::new, Segmenter::into_completed),
+        }
+    }
+
+    /// Where a segmented recording carries on once the stream is reopened
+    /// after the device was lost: its next segment. Other recordings
+    /// cannot continue; a plain file is read back and deleted by
+    /// `Recorder::stop_recording`, so it has to stay a single file.
+    fn continuation(&self, armed: bool, paused: bool) -> Option<Continuation> {
+        let segments = self.segments.as_ref()?;
+        Some(Continuation {
+            path: segments.path().to_string(),
+            limit: segments.limit(),
+            index: segments.index() + 1,
+            completed: Vec::new(),
+            format: self.format(),
+            frames_left: self
+                .max_frames
+                .map(|max| max.saturating_sub(self.frames_written)),
+            armed,
+            paused,
+        })
+    }
+
+    /// Writes out what the resampler still holds, rotating segments as
+    /// usual.
+    fn flush_resampler(&mut self) -> hound::Result<()> {
+        let Some(resampler) = &mut self.resampler else {
+            return Ok(());
+        };
+        let mut tail = Vec::new();
+        resampler.flush(&mut tail);
+        self.write_output(&tail)
+    }
+
+    /// Writes the final header. In-memory recordings return the finished
+    /// bytes.
+    fn finalize(self) -> hound::Result<Option<Vec<u8>>> {
+        self.writer.finalize()?;
+        Ok(self.memory.map(|buffer| buffer.take()))
+    }
+}
+
+/// An interrupted segmented recording, waiting for the stream to come back.
+struct Continuation {
+    path: String,
+    limit: SegmentLimit,
+    /// Segment number the recording continues in.
+    index: u32,
+    /// Segments finalized before the device was lost.
+    completed: Vec<CompletedSegment>,
+    format: OutputFormat,
+    /// Output frames left before `max_duration` is reached.
+    frames_left: Option<u64>,
+    armed: bool,
+    paused: bool,
+}
+
+/// A recording the input callback finalized on its own, held until
+/// `StopRecording` or `CancelRecording` collects it.
+pub(crate) struct FinishedRecording {
+    pub(crate) output: RecordingOutput,
+    pub(crate) format: OutputFormat,
+    pub(crate) input_stats: InputStats,
+    pub(crate) result: hound::Result<Option<Vec<u8>>>,
+    /// Segment files written, empty unless the recording was segmented.
+    pub(crate) segments: Vec<CompletedSegment>,
+}
+
+/// State shared between the audio thread and the input callback of one
+/// recording session. The stream keeps running between recordings; samples
+/// are only written while a recording is active, and otherwise go into the
+/// pre-roll buffer.
+pub(crate) struct Capture {
+    input_channels: u16,
+    input_sample_rate: u32,
+    channel_policy: ChannelPolicy,
+    /// Channels after the channel policy, before resampling.
+    output_channels: u16,
+    resample_quality: ResampleQuality,
+    normalized: Vec<f32>,
+    mixed: Vec<f32>,
+    active: Option<ActiveRecording>,
+    pre_roll: PreRoll,
+    /// Pre-roll taken out of the buffer for writing, reused so the input
+    /// callback does not allocate.
+    pre_roll_drained: Vec<f32>,
+    vad_config: VadConfig,
+    vad: VoiceActivityDetector,
+    health_config: InputHealthConfig,
+    /// Trailing silence, in input frames, after which the recording stops.
+    auto_stop_frames: Option<u64>,
+    finished: Option<FinishedRecording>,
+    /// Set once the input device is gone. Nothing more is captured.
+    device_lost: bool,
+    /// Whether the session reconnects after losing the device, so an
+    /// interrupted segmented recording should continue rather than stop.
+    reconnect: bool,
+    continuation: Option<Continuation>,
+    /// Armed recordings have an open writer but wait for speech before
+    /// writing anything.
+    armed: bool,
+    events: EventBus,
+    meter: Meter,
+    chunks: ChunkSubscribers,
+    /// While paused the stream keeps running but nothing reaches the writer.
+    paused: bool,
+}
+
+impl Capture {
+    pub(crate) fn new(
+        input: &cpal::SupportedStreamConfig,
+        config: &UserRecordingSessionConfig,
+        events: EventBus,
+        meters: EventBus<MeterReading>,
+        chunks: ChunkSubscribers,
+    ) -> Self {
+        let input_sample_rate = input.sample_rate().0;
+        let output_channels = config.channel_policy.output_channels(input.channels());
+        let pre_roll = PreRoll::new(config.pre_roll, input_sample_rate, output_channels);
+        Self {
+            input_channels: input.channels(),
+            input_sample_rate,
+            channel_policy: config.channel_policy.clone(),
+            output_channels,
+            resample_quality: config.resample_quality,
+            normalized: Vec::new(),
+            mixed: Vec::new(),
+            active: None,
+            pre_roll_drained: Vec::with_capacity(pre_roll.capacity()),
+            pre_roll,
+            vad_config: config.vad.clone(),
+            vad: VoiceActivityDetector::new(&config.vad, input_sample_rate),
+            health_config: config.input_health.clone(),
+            auto_stop_frames: config
+                .auto_stop_after
+                .map(|silence| vad::duration_to_frames(silence, input_sample_rate)),
+            finished: None,
+            device_lost: false,
+            reconnect: config.reconnect.is_some(),
+            continuation: None,
+            armed: false,
+            events,
+            meter: Meter::new(
+                input.channels(),
+                input_sample_rate,
+                config.meter_rate_hz,
+                meters,
+            ),
+            chunks,
+            paused: false,
+        }
+    }
+
+    /// Handles one block from the input callback.
+    pub(crate) fn process(&mut self, input: InputBuffer<'_>) -> hound::Result<()> {
+        if self.device_lost {
+            return Ok(());
+        }
+        let writing = self.active.is_some() && !self.paused;
+        let chunking = self.chunks.is_enabled();
+        if !writing && !self.pre_roll.is_enabled() && !self.meter.is_enabled() && !chunking {
+            return Ok(());
+        }
+
+        self.normalized.clear();
+        sample::append_normalized(input, &mut self.normalized);
+        self.meter.process(&self.normalized);
+        if !chunking && (self.paused || (self.active.is_none() && !self.pre_roll.is_enabled())) {
+            return Ok(());
+        }
+
+        let samples = if self.channel_policy == ChannelPolicy::KeepAll {
+            &self.normalized
+        } else {
+            self.mixed.clear();
+            self.channel_policy
+                .apply(self.input_channels, &self.normalized, &mut self.mixed);
+            &self.mixed
+        };
+        self.chunks
+            .process(samples, self.output_channels, self.input_sample_rate);
+        if self.paused {
+            return Ok(());
+        }
+        let Some(active) = &mut self.active else {
+            self.pre_roll.push(samples);
+            return Ok(());
+        };
+        if self.armed {
+            if vad::rms_dbfs(samples) < self.vad_config.threshold_dbfs {
+                self.pre_roll.push(samples);
+                return Ok(());
+            }
+            self.armed = false;
+            self.pre_roll.drain_into(&mut self.pre_roll_drained);
+            active.write(&self.pre_roll_drained)?;
+            self.vad = VoiceActivityDetector::new(&self.vad_config, self.input_sample_rate);
+            self.events.emit(RecorderEvent::RecordingTriggered);
+        }
+        active.write(samples)?;
+        active.monitor.process(samples, |warning| {
+            self.events.emit(RecorderEvent::InputWarning(warning))
+        });
+        if active.limit_reached() {
+            self.finish(AutoStopReason::MaxDuration);
+            return Ok(());
+        }
+
+        if let Some(limit) = self.auto_stop_frames {
+            self.vad.process(samples, self.output_channels);
+            if self.vad.silent_frames() >= limit {
+                self.finish(AutoStopReason::Silence);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finalizes the active recording from the input callback and tells
+    /// subscribers it is ready to collect.
+    fn finish(&mut self, reason: AutoStopReason) {
+        let Some(active) = self.take() else {
+            return;
+        };
+        self.finished = Some(active.finish());
+        self.events
+            .emit(RecorderEvent::RecordingAutoStopped(reason));
+    }
+
+    /// Finalizes the active recording after the input device disappeared,
+    /// so everything captured so far is kept, and puts the session in the
+    /// error state. When the session reconnects, a segmented recording is
+    /// set aside to continue in its next segment; anything else is left
+    /// for `StopRecording` to collect.
+    pub(crate) fn device_lost(&mut self) {
+        if self.device_lost {
+            return;
+        }
+        self.device_lost = true;
+        let (armed, paused) = (self.armed, self.paused);
+        let Some(active) = self.take() else {
+            return;
+        };
+        let mut continuation = self
+            .reconnect
+            .then(|| active.continuation(armed, paused))
+            .flatten();
+        let finished = active.finish();
+        if let Some(continuation) = &mut continuation {
+            continuation.completed = finished.segments.clone();
+        }
+        self.continuation = continuation;
+        self.finished = Some(finished);
+        if self.continuation.is_none() {
+            self.events.emit(RecorderEvent::RecordingAutoStopped(
+                AutoStopReason::DeviceLost,
+            ));
+        }
+    }
+
+    pub(crate) fn is_device_lost(&self) -> bool {
+        self.device_lost
+    }
+
+    /// Gives up on continuing an interrupted recording, e.g. because
+    /// reconnecting failed. What was captured waits to be collected.
+    pub(crate) fn abandon_continuation(&mut self) {
+        if self.continuation.take().is_some() {
+            self.events.emit(RecorderEvent::RecordingAutoStopped(
+                AutoStopReason::DeviceLost,
+            ));
+        }
+    }
+
+    /// Takes over what `lost`, the capture of the stream that died, left
+    /// behind. An interrupted segmented recording continues in its next
+    /// segment, whose path is returned; a finalized recording waits to be
+    /// collected from this capture instead.
+    pub(crate) fn take_over(&mut self, lost: &mut Capture, spec: hound::WavSpec) -> Option<String> {
+        self.finished = lost.finished.take();
+        let continuation = lost.continuation.take()?;
+        match self.continue_recording(continuation, spec) {
+            Ok(path) => {
+                // The earlier segments are complete on disk and carried
+                // over; only a failure to finalize them is worth reporting.
+                if let Some(Err(e)) = self.finished.take().map(|finished| finished.result) {
+                    self.events.emit(RecorderEvent::StreamError(format!(
+                        "Failed to finalize recording: {}",
+                        e
+                    )));
+                }
+                Some(path)
+            }
+            Err(e) => {
+                self.events.emit(RecorderEvent::StreamError(format!(
+                    "Failed to continue recording: {}",
+                    e
+                )));
+                self.events.emit(RecorderEvent::RecordingAutoStopped(
+                    AutoStopReason::DeviceLost,
+                ));
+                None
+            }
+        }
+    }
+
+    fn continue_recording(
+        &mut self,
+        continuation: Continuation,
+        spec: hound::WavSpec,
+    ) -> hound::Result<String> {
+        continuation
+            .format
+            .validate(&spec)
+            .map_err(|e| hound::Error::IoError(std::io::Error::other(e)))?;
+        let sample_rate = continuation.format.sample_rate(&spec);
+        let mut segments = Segmenter::new(
+            continuation.path.clone(),
+            continuation.limit,
+            hound::WavSpec {
+                sample_rate,
+                ..spec
+            },
+            self.events.clone(),
+        );
+        segments.continue_from(continuation.index, continuation.completed);
+        let path = segments.current_path();
+        let active = self.open_output(
+            RecordingOutput::Segments(continuation.path),
+            Some(segments),
+            RecordingTarget::File(path.clone()),
+            spec,
+            continuation.format,
+            continuation.frames_left,
+        )?;
+        self.activate(active, continuation.armed);
+        self.paused = continuation.paused;
+        Ok(path)
+    }
+
+    /// Starts encoding `spec` audio to `target` in `options.format`,
+    /// resampling if the encoded rate differs from the input. Buffered
+    /// pre-roll is written first.
+    pub(crate) fn start(
+        &mut self,
+        target: RecordingTarget,
+        spec: hound::WavSpec,
+        options: &RecordingOptions,
+    ) -> hound::Result<()> {
+        let mut active = self.open(target, spec, options)?;
+        self.pre_roll.drain_into(&mut self.pre_roll_drained);
+        active.write(&self.pre_roll_drained)?;
+        self.activate(active, false);
+        Ok(())
+    }
+
+    /// Opens the output like `start`, but writes nothing until the input level
+    /// crosses the speech threshold. The pre-roll leading up to the trigger
+    /// is kept.
+    pub(crate) fn arm(
+        &mut self,
+        target: RecordingTarget,
+        spec: hound::WavSpec,
+        options: &RecordingOptions,
+    ) -> hound::Result<()> {
+        let active = self.open(target, spec, options)?;
+        self.activate(active, true);
+        Ok(())
+    }
+
+    fn activate(&mut self, active: ActiveRecording, armed: bool) {
+        self.active = Some(active);
+        self.armed = armed;
+        self.vad = VoiceActivityDetector::new(&self.vad_config, self.input_sample_rate);
+    }
+
+    fn open(
+        &self,
+        target: RecordingTarget,
+        spec: hound::WavSpec,
+        options: &RecordingOptions,
+    ) -> hound::Result<ActiveRecording> {
+        let output = options.output(&target);
+        let sample_rate = options.format.sample_rate(&spec);
+        let segments = match (&output, options.segment) {
+            (RecordingOutput::Segments(path), Some(limit)) => Some(Segmenter::new(
+                path.clone(),
+                limit,
+                hound::WavSpec {
+                    sample_rate,
+                    ..spec
+                },
+                self.events.clone(),
+            )),
+            _ => None,
+        };
+        self.open_output(
+            output,
+            segments,
+            target,
+            spec,
+            options.format,
+            options
+                .max_duration
+                .map(|max| vad::duration_to_frames(max, sample_rate)),
+        )
+    }
+
+    fn open_output(
+        &self,
+        output: RecordingOutput,
+        segments: Option<Segmenter>,
+        target: RecordingTarget,
+        spec: hound::WavSpec,
+        format: OutputFormat,
+        max_frames: Option<u64>,
+    ) -> hound::Result<ActiveRecording> {
+        let sample_rate = format.sample_rate(&spec);
+        let (sink, memory) = match &segments {
+            Some(segments) => RecordingTarget::File(segments.current_path()).open()?,
+            None => target.open()?,
+        };
+        let writer = Encoder::new(format, sink, spec)?;
+        let resampler = (sample_rate != self.input_sample_rate).then(|| {
+            Resampler::new(
+                spec.channels,
+                self.input_sample_rate,
+                sample_rate,
+                self.resample_quality,
+            )
+        });
+        Ok(ActiveRecording {
+            output,
+            writer,
+            memory,
+            resampler,
+            resampled: Vec::new(),
+            frames_written: 0,
+            max_frames,
+            segments,
+            monitor: InputMonitor::new(
+                &self.health_config,
+                self.output_channels,
+                self.input_sample_rate,
+            ),
+        })
+    }
+
+    pub(crate) fn active(&self) -> Option<&ActiveRecording> {
+        self.active.as_ref()
+    }
+
+    pub(crate) fn take(&mut self) -> Option<ActiveRecording> {
+        self.paused = false;
+        self.armed = false;
+        self.active.take()
+    }
+
+    /// Whether a recording finalized without `StopRecording` is waiting to
+    /// be collected.
+    pub(crate) fn has_finished(&self) -> bool {
+        self.finished.is_some()
+    }
+
+    /// Collects a recording finalized without `StopRecording`. A pending
+    /// continuation is dropped, since the caller has ended the recording.
+    pub(crate) fn take_finished(&mut self) -> Option<FinishedRecording> {
+        self.continuation = None;
+        self.finished.take()
+    }
+
+    pub(crate) fn pause(&mut self) -> Result<(), String> {
+        match self.state() {
+            RecordingState::Recording => {
+                self.paused = true;
+                Ok(())
+            }
+            RecordingState::Paused => Err("Recording already paused".to_string()),
+            RecordingState::Armed => Err("Armed recording has not started yet".to_string()),
+            _ => Err("No active recording to pause".to_string()),
+        }
+    }
+
+    pub(crate) fn resume(&mut self) -> Result<(), String> {
+        match self.state() {
+            RecordingState::Paused => {
+                self.paused = false;
+                Ok(())
+            }
+            RecordingState::Recording => Err("Recording is not paused".to_string()),
+            _ => Err("No active recording to resume".to_string()),
+        }
+    }
+
+    pub(crate) fn state(&self) -> RecordingState {
+        if self.device_lost {
+            return RecordingState::Error("Input device lost".to_string());
+        }
+        match (&self.active, self.armed, self.paused) {
+            (None, _, _) => RecordingState::Initialized,
+            (Some(_), true, _) => RecordingState::Armed,
+            (Some(_), false, false) => RecordingState::Recording,
+            (Some(_), false, true) => RecordingState::Paused,
+        }
+    }
+}
@@ -0,0 +1,114 @@
+/// Which input channels end up in the recording.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ChannelPolicy {
+    /// Record every channel the device delivers, interleaved.
+    #[default]
+    KeepAll,
+    /// Average all input channels into a single channel.
+    DownmixMono,
+    /// Record only these input channels (zero-based), in this order.
+    Select(Vec<u16>),
+}
+
+impl ChannelPolicy {
+    /// Checks the policy against the device's channel count.
+    pub fn validate(&self, input_channels: u16) -> Result<(), String> {
+        match self {
+            ChannelPolicy::Select(indices) if indices.is_empty() => {
+                Err("Channel selection must name at least one channel".to_string())
+            }
+            ChannelPolicy::Select(indices) => {
+                match indices.iter().find(|&&i| i >= input_channels) {
+                    Some(index) => Err(format!(
+                        "Channel {} out of range: device has {} channels",
+                        index, input_channels
+                    )),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn output_channels(&self, input_channels: u16) -> u16 {
+        match self {
+            ChannelPolicy::KeepAll => input_channels,
+            ChannelPolicy::DownmixMono => 1,
+            ChannelPolicy::Select(indices) => indices.len() as u16,
+        }
+    }
+
+    /// Applies the policy to interleaved `input` and appends the result to `output`.
+    pub fn apply(&self, input_channels: u16, input: &[f32], output: &mut Vec<f32>) {
+        let input_channels = input_channels.max(1) as usize;
+        match self {
+            ChannelPolicy::KeepAll => output.extend_from_slice(input),
+            ChannelPolicy::DownmixMono => output.extend(
+                input
+                    .chunks_exact(input_channels)
+                    .map(|frame| frame.iter().sum::<f32>() / input_channels as f32),
+            ),
+            ChannelPolicy::Select(indices) => {
+                for frame in input.chunks_exact(input_channels) {
+                    output.extend(indices.iter().map(|&i| frame[i as usize]));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three stereo frames with distinct left and right values.
+    const STEREO: [f32; 6] = [0.5, -0.25, 1.0, 0.0, -1.0, 0.5];
+
+    fn apply(policy: ChannelPolicy, input_channels: u16, input: &[f32]) -> Vec<f32> {
+        let mut output = Vec::new();
+        policy.apply(input_channels, input, &mut output);
+        output
+    }
+
+    #[test]
+    fn keep_all_passes_samples_through() {
+        assert_eq!(apply(ChannelPolicy::KeepAll, 2, &STEREO), STEREO);
+        assert_eq!(ChannelPolicy::KeepAll.output_channels(6), 6);
+        assert!(ChannelPolicy::KeepAll.validate(1).is_ok());
+    }
+
+    #[test]
+    fn downmix_averages_each_frame() {
+        assert_eq!(
+            apply(ChannelPolicy::DownmixMono, 2, &STEREO),
+            [0.125, 0.5, -0.25]
+        );
+        assert_eq!(
+            apply(ChannelPolicy::DownmixMono, 3, &[0.3, 0.6, 0.0]),
+            [0.3]
+        );
+        assert_eq!(ChannelPolicy::DownmixMono.output_channels(6), 1);
+    }
+
+    #[test]
+    fn select_reorders_and_repeats_channels() {
+        let policy = ChannelPolicy::Select(vec![1, 0, 1]);
+        assert!(policy.validate(2).is_ok());
+        assert_eq!(policy.output_channels(2), 3);
+        assert_eq!(
+            apply(policy, 2, &STEREO),
+            [-0.25, 0.5, -0.25, 0.0, 1.0, 0.0, 0.5, -1.0, 0.5]
+        );
+        assert_eq!(
+            apply(ChannelPolicy::Select(vec![1]), 2, &STEREO),
+            [-0.25, 0.0, 0.5]
+        );
+    }
+
+    #[test]
+    fn select_rejects_empty_and_out_of_range_indices() {
+        assert!(ChannelPolicy::Select(Vec::new()).validate(2).is_err());
+        assert!(ChannelPolicy::Select(vec![0, 2]).validate(2).is_err());
+        assert!(ChannelPolicy::Select(vec![1, 1]).validate(2).is_ok());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::resample::{ResampleQuality, Resampler};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+/// Input blocks queued for the chunk worker before newer ones are dropped.
+const BLOCK_QUEUE_CAPACITY: usize = 64;
+
+/// Shape of the PCM chunks delivered to a streaming consumer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkConfig {
+    /// Sample rate of the delivered audio, e.g. 16000 for speech recognizers.
+    pub sample_rate: u32,
+    /// Length of every chunk, e.g. 30 ms.
+    pub chunk_duration: Duration,
+    /// Chunks queued for a slow consumer before newer ones are dropped.
+    pub capacity: usize,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 16_000,
+            chunk_duration: Duration::from_millis(30),
+            capacity: 64,
+        }
+    }
+}
+
+/// A fixed-size block of mono f32 samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcmChunk {
+    /// Increments by one per chunk produced. A gap means the consumer fell
+    /// behind and chunks were dropped.
+    pub sequence: u64,
+    pub samples: Vec<f32>,
+}
+
+struct ChunkSubscriber {
+    config: ChunkConfig,
+    chunk_len: usize,
+    sender: SyncSender<PcmChunk>,
+    /// Converter from the input rate it was built for.
+    resampler: Option<(u32, Resampler)>,
+    resampled: Vec<f32>,
+    pending: Vec<f32>,
+    sequence: u64,
+}
+
+impl ChunkSubscriber {
+    /// Starts over after input blocks were dropped, leaving a gap in
+    /// `sequence` so the consumer can tell.
+    fn skip(&mut self) {
+        self.resampler = None;
+        self.pending.clear();
+        self.sequence += 1;
+    }
+
+    /// Returns false once the receiver has been dropped.
+    fn process(&mut self, mono: &[f32], sample_rate: u32) -> bool {
+        let samples = if sample_rate == self.config.sample_rate {
+            mono
+        } else {
+            if self.resampler.as_ref().map(|(rate, _)| *rate) != Some(sample_rate) {
+                let resampler = Resampler::new(
+                    1,
+                    sample_rate,
+                    self.config.sample_rate,
+                    ResampleQuality::High,
+                );
+                self.resampler = Some((sample_rate, resampler));
+            }
+            self.resampled.clear();
+            if let Some((_, resampler)) = &mut self.resampler {
+                resampler.process(mono, &mut self.resampled);
+            }
+            &self.resampled
+        };
+        self.pending.extend_from_slice(samples);
+
+        let mut start = 0;
+        while self.pending.len() - start >= self.chunk_len {
+            let chunk = PcmChunk {
+                sequence: self.sequence,
+                samples: self.pending[start..start + self.chunk_len].to_vec(),
+            };
+            self.sequence += 1;
+            start += self.chunk_len;
+            if let Err(TrySendError::Disconnected(_)) = self.sender.try_send(chunk) {
+                return false;
+            }
+        }
+        self.pending.drain(..start);
+        true
+    }
+}
+
+/// A block from the input callback, waiting for the chunk worker.
+struct Block {
+    samples: Vec<f32>,
+    channels: u16,
+    sample_rate: u32,
+    /// Blocks were dropped right before this one.
+    after_gap: bool,
+}
+
+/// Consumers of live PCM chunks, shared between the recorder and the input
+/// callback. The callback only copies each block into a bounded queue;
+/// downmixing, resampling and chunking happen on a worker thread started
+/// with the first subscription. Neither the callback nor the worker blocks
+/// on a consumer: chunks go through a bounded channel per subscriber and
+/// are dropped when it is full.
+#[derive(Clone, Default)]
+pub struct ChunkSubscribers {
+    subscribers: Arc<Mutex<Vec<ChunkSubscriber>>>,
+    /// Feeds the worker. `None` until something subscribes.
+    queue: Arc<Mutex<Option<SyncSender<Block>>>>,
+    /// Set when the worker fell behind and a block was dropped, until the
+    /// next block gets through.
+    overflowed: Arc<AtomicBool>,
+}
+
+impl ChunkSubscribers {
+    pub fn subscribe(&self, config: ChunkConfig) -> Receiver<PcmChunk> {
+        let (sender, receiver) = mpsc::sync_channel(config.capacity.max(1));
+        let chunk_len = ((config.chunk_duration.as_secs_f64() * config.sample_rate as f64).round()
+            as usize)
+            .max(1);
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(ChunkSubscriber {
+                config,
+                chunk_len,
+                sender,
+                resampler: None,
+                resampled: Vec::new(),
+                pending: Vec::new(),
+                sequence: 0,
+            });
+        }
+        self.start_worker();
+        receiver
+    }
+
+    fn start_worker(&self) {
+        let Ok(mut queue) = self.queue.lock() else {
+            return;
+        };
+        if queue.is_some() {
+            return;
+        }
+        let (sender, blocks) = mpsc::sync_channel(BLOCK_QUEUE_CAPACITY);
+        let subscribers = self.subscribers.clone();
+        let spawned = std::thread::Builder::new()
+            .name("audio-chunks".to_string())
+            .spawn(move || run_worker(blocks, subscribers));
+        match spawned {
+            Ok(_) => *queue = Some(sender),
+            Err(e) => warn!("Failed to start chunk worker: {}", e),
+        }
+    }
+
+    /// Whether anything has subscribed, so the callback has blocks to queue.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.queue.lock().is_ok_and(|queue| queue.is_some())
+    }
+
+    /// Queues interleaved samples for the worker. Called from the input
+    /// callback, so it never waits for the worker to catch up.
+    pub(crate) fn process(&self, samples: &[f32], channels: u16, sample_rate: u32) {
+        let Ok(queue) = self.queue.lock() else {
+            return;
+        };
+        let Some(queue) = queue.as_ref() else {
+            return;
+        };
+        let block = Block {
+            samples: samples.to_vec(),
+            channels,
+            sample_rate,
+            after_gap: self.overflowed.load(Ordering::Relaxed),
+        };
+        match queue.try_send(block) {
+            Ok(()) => self.overflowed.store(false, Ordering::Relaxed),
+            Err(TrySendError::Full(_)) => self.overflowed.store(true, Ordering::Relaxed),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+/// Downmixes queued blocks to mono and feeds every subscriber, until all
+/// handles to the queue are dropped.
+fn run_worker(blocks: Receiver<Block>, subscribers: Arc<Mutex<Vec<ChunkSubscriber>>>) {
+    let mut mono = Vec::new();
+    for block in blocks {
+        let Ok(mut subscribers) = subscribers.lock() else {
+            return;
+        };
+        if block.after_gap {
+            subscribers.iter_mut().for_each(ChunkSubscriber::skip);
+        }
+        if subscribers.is_empty() {
+            continue;
+        }
+        let channels = block.channels.max(1) as usize;
+        mono.clear();
+        mono.extend(
+            block
+                .samples
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+        subscribers.retain_mut(|subscriber| subscriber.process(&mono, block.sample_rate));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::flac::{self, FlacEncoder};
+#[cfg(feature = "opus")]
+use crate::opus::OpusEncoder;
+use crate::opus::{OpusConfig, OPUS_SAMPLE_RATE};
+use crate::sample;
+use crate::sink::RecordingSink;
+
+/// Container and codec a recording is encoded with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Wav,
+    /// Lossless FLAC, encoded block by block while recording. Needs 16 or
+    /// 24-bit integer samples.
+    Flac,
+    /// Lossy Opus in an Ogg container, for compact speech uploads. Always
+    /// encoded at 48 kHz, resampling if needed; mono or stereo only. Needs
+    /// the `opus` feature.
+    Opus(OpusConfig),
+}
+
+impl OutputFormat {
+    /// File extension without the dot, e.g. for `<id>.<extension>`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Wav => "wav",
+            OutputFormat::Flac => "flac",
+            OutputFormat::Opus(_) => "opus",
+        }
+    }
+
+    /// MIME type of the encoded bytes, e.g. for an upload's `Content-Type`.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Wav => "audio/wav",
+            OutputFormat::Flac => "audio/flac",
+            OutputFormat::Opus(_) => "audio/ogg",
+        }
+    }
+
+    /// Checks that the session's spec can be encoded in this format.
+    pub fn validate(&self, spec: &hound::WavSpec) -> Result<(), String> {
+        match self {
+            OutputFormat::Wav => Ok(()),
+            OutputFormat::Flac => flac::validate_spec(spec),
+            OutputFormat::Opus(config) => config.validate(spec.channels),
+        }
+    }
+
+    /// Rate the samples are encoded at for a session producing `spec`.
+    pub fn sample_rate(&self, spec: &hound::WavSpec) -> u32 {
+        match self {
+            OutputFormat::Opus(_) => OPUS_SAMPLE_RATE,
+            _ => spec.sample_rate,
+        }
+    }
+}
+
+/// Incremental encoder for one output file or buffer.
+pub(crate) enum Encoder {
+    Wav(hound::WavWriter<Box<dyn RecordingSink>>),
+    Flac(FlacEncoder<Box<dyn RecordingSink>>),
+    #[cfg(feature = "opus")]
+    Opus(OpusEncoder<Box<dyn RecordingSink>>),
+}
+
+impl Encoder {
+    /// Opens an encoder for a session producing `spec`. Samples passed to
+    /// `write` must already be at `format.sample_rate(&spec)`.
+    pub(crate) fn new(
+        format: OutputFormat,
+        sink: Box<dyn RecordingSink>,
+        spec: hound::WavSpec,
+    ) -> hound::Result<Self> {
+        Ok(match format {
+            OutputFormat::Wav => Encoder::Wav(hound::WavWriter::new(sink, spec)?),
+            OutputFormat::Flac => Encoder::Flac(FlacEncoder::new(sink, spec)?),
+            #[cfg(feature = "opus")]
+            OutputFormat::Opus(config) => Encoder::Opus(OpusEncoder::new(
+                sink,
+                spec.channels,
+                spec.sample_rate,
+                config,
+            )?),
+            #[cfg(not(feature = "opus"))]
+            OutputFormat::Opus(_) => {
+                return Err(hound::Error::IoError(std::io::Error::other(
+                    "Opus output needs the `opus` cargo feature",
+                )))
+            }
+        })
+    }
+
+    /// Opens a fresh encoder with the same settings, e.g. for the next
+    /// segment.
+    pub(crate) fn reopen(&self, sink: Box<dyn RecordingSink>) -> hound::Result<Self> {
+        Ok(match self {
+            Encoder::Wav(writer) => Encoder::Wav(hound::WavWriter::new(sink, writer.spec())?),
+            Encoder::Flac(encoder) => Encoder::Flac(FlacEncoder::new(sink, encoder.spec())?),
+            #[cfg(feature = "opus")]
+            Encoder::Opus(encoder) => Encoder::Opus(encoder.reopen(sink)?),
+        })
+    }
+
+    pub(crate) fn format(&self) -> OutputFormat {
+        match self {
+            Encoder::Wav(_) => OutputFormat::Wav,
+            Encoder::Flac(_) => OutputFormat::Flac,
+            #[cfg(feature = "opus")]
+            Encoder::Opus(encoder) => OutputFormat::Opus(encoder.config()),
+        }
+    }
+
+    pub(crate) fn channels(&self) -> u16 {
+        match self {
+            Encoder::Wav(writer) => writer.spec().channels,
+            Encoder::Flac(encoder) => encoder.spec().channels,
+            #[cfg(feature = "opus")]
+            Encoder::Opus(encoder) => encoder.channels(),
+        }
+    }
+
+    /// Encodes interleaved samples scaled to [-1.0, 1.0).
+    pub(crate) fn write(&mut self, samples: &[f32]) -> hound::Result<()> {
+        match self {
+            Encoder::Wav(writer) => sample::write_normalized(writer, samples),
+            Encoder::Flac(encoder) => Ok(encoder.write(samples)?),
+            #[cfg(feature = "opus")]
+            Encoder::Opus(encoder) => Ok(encoder.write(samples)?),
+        }
+    }
+
+    /// Flushes buffered samples and patches or closes the container.
+    pub(crate) fn finalize(self) -> hound::Result<()> {
+        match self {
+            Encoder::Wav(writer) => writer.finalize(),
+            Encoder::Flac(encoder) => Ok(encoder.finalize()?),
+            #[cfg(feature = "opus")]
+            Encoder::Opus(encoder) => Ok(encoder.finalize()?),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::backend::DeviceId;
+use crate::health::InputWarning;
+use crate::segment::CompletedSegment;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Unsolicited notifications from the audio thread. These never travel on the
+/// request/response channel, so they cannot be mistaken for a reply.
+#[derive(Debug, Clone)]
+pub enum RecorderEvent {
+    /// The input device disappeared, e.g. a USB headset was unplugged.
+    DeviceLost,
+    /// The backend reported that input samples were dropped.
+    Overrun(String),
+    /// Any other error reported by the running stream.
+    StreamError(String),
+    /// The recording's input is clipping or dead. Raised while recording, so
+    /// the user can fix it before the take is lost.
+    InputWarning(InputWarning),
+    /// A segment file of a segmented recording was finalized and can be
+    /// picked up, e.g. for transcription.
+    SegmentCompleted(CompletedSegment),
+    /// An armed recording heard speech and started writing.
+    RecordingTriggered,
+    /// The recording was finalized without a `StopRecording`. Call
+    /// `stop_recording` to collect it as usual.
+    RecordingAutoStopped(AutoStopReason),
+    /// About to try reopening the input stream on `device` after
+    /// `DeviceLost`. `attempt` counts from 1.
+    Reconnecting { attempt: u32, device: DeviceId },
+    /// The input stream is running again on `device`. A segmented recording
+    /// that was interrupted carries on in `continued_in`.
+    Reconnected {
+        device: DeviceId,
+        continued_in: Option<String>,
+    },
+    /// Reconnecting was given up after `attempts` tries. The session stays
+    /// in the error state until it is closed.
+    ReconnectFailed { attempts: u32, error: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoStopReason {
+    /// Trailing silence reached the configured auto-stop duration.
+    Silence,
+    /// The recording reached its `max_duration`, to the exact frame.
+    MaxDuration,
+    /// The input device disappeared. Everything captured up to that point
+    /// was kept.
+    DeviceLost,
+}
+
+/// Backend error messages that mean the device is gone. ALSA, for one,
+/// reports an unplugged device as `ENODEV` rather than `DeviceNotAvailable`.
+const DEVICE_LOST_ERRORS: &[&str] = &[
+    "no such device",
+    "enodev",
+    "errno 19",
+    "device not available",
+    "device is not available",
+    "disconnected",
+    "invalidated",
+];
+
+impl RecorderEvent {
+    pub fn from_stream_error(err: cpal::StreamError) -> Self {
+        match err {
+            cpal::StreamError::DeviceNotAvailable => RecorderEvent::DeviceLost,
+            cpal::StreamError::BackendSpecific { err } => {
+                let description = err.description.to_lowercase();
+                if DEVICE_LOST_ERRORS
+                    .iter()
+                    .any(|pattern| description.contains(pattern))
+                {
+                    RecorderEvent::DeviceLost
+                } else if description.contains("overrun") || description.contains("xrun") {
+                    RecorderEvent::Overrun(err.description)
+                } else {
+                    RecorderEvent::StreamError(err.description)
+                }
+            }
+        }
+    }
+}
+
+/// Fans events out to every live subscriber. Subscribers that dropped their
+/// receiver are pruned on the next emit.
+pub struct EventBus<T = RecorderEvent> {
+    subscribers: Arc<Mutex<Vec<Sender<T>>>>,
+}
+
+impl<T> Clone for EventBus<T> {
+    fn clone(&self) -> Self {
+        Self {
+            subscribers: Arc::clone(&self.subscribers),
+        }
+    }
+}
+
+impl<T> Default for EventBus<T> {
+    fn default() -> Self {
+        Self {
+            subscribers: Arc::default(),
+        }
+    }
+}
+
+impl<T: Clone> EventBus<T> {
+    pub fn subscribe(&self) -> Receiver<T> {
+        let (tx, rx) = mpsc::channel();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(tx);
+        }
+        rx
+    }
+
+    pub fn emit(&self, event: T) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend_error(description: &str) -> RecorderEvent {
+        RecorderEvent::from_stream_error(cpal::StreamError::BackendSpecific {
+            err: cpal::BackendSpecificError {
+                description: description.to_string(),
+            },
+        })
+    }
+
+    #[test]
+    fn classifies_stream_errors() {
+        assert!(matches!(
+            RecorderEvent::from_stream_error(cpal::StreamError::DeviceNotAvailable),
+            RecorderEvent::DeviceLost
+        ));
+        assert!(matches!(
+            backend_error("`alsa::poll()` spuriously returned: ENODEV: No such device"),
+            RecorderEvent::DeviceLost
+        ));
+        assert!(matches!(
+            backend_error("A buffer overrun occurred"),
+            RecorderEvent::Overrun(_)
+        ));
+        assert!(matches!(
+            backend_error("Unexpected poll revents"),
+            RecorderEvent::StreamError(_)
+        ));
+    }
+
+    #[test]
+    fn emits_to_every_live_subscriber() {
+        let bus = EventBus::default();
+        let first = bus.subscribe();
+        let dropped = bus.subscribe();
+        drop(dropped);
+        let second = bus.subscribe();
+
+        bus.emit(RecorderEvent::RecordingTriggered);
+        assert!(matches!(
+            first.try_recv(),
+            Ok(RecorderEvent::RecordingTriggered)
+        ));
+        assert!(matches!(
+            second.try_recv(),
+            Ok(RecorderEvent::RecordingTriggered)
+        ));
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 2);
+    }
+}
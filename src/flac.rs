@@ -0,0 +1,453 @@
+use crate::sample;
+use std::io::{Seek, SeekFrom, Write};
+
+/// Frames per FLAC block. 4096 is the reference encoder's default.
+const BLOCK_SIZE: usize = 4096;
+/// Highest Rice parameter expressible with the 4-bit residual coding method.
+const MAX_RICE_PARAMETER: u32 = 14;
+const MAX_FIXED_ORDER: usize = 4;
+
+/// Big-endian bit packer for frame headers and subframes.
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            accumulator: 0,
+            bits: 0,
+        }
+    }
+
+    fn write(&mut self, value: u64, bits: u32) {
+        for shift in (0..bits).rev() {
+            self.accumulator = (self.accumulator << 1) | ((value >> shift) & 1);
+            self.bits += 1;
+            if self.bits == 8 {
+                self.bytes.push(self.accumulator as u8);
+                self.accumulator = 0;
+                self.bits = 0;
+            }
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64 & ((1u64 << bits) - 1), bits);
+    }
+
+    fn write_unary(&mut self, zeros: u64) {
+        for _ in 0..zeros {
+            self.write(0, 1);
+        }
+        self.write(1, 1);
+    }
+
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(0, 8 - self.bits);
+        }
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Checks that the spec can be stored as FLAC.
+pub(crate) fn validate_spec(spec: &hound::WavSpec) -> Result<(), String> {
+    match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Int, 16 | 24) => {}
+        _ => {
+            return Err(format!(
+                "FLAC output needs 16 or 24-bit integer samples, got {}-bit {:?}",
+                spec.bits_per_sample, spec.sample_format
+            ))
+        }
+    }
+    if !(1..=8).contains(&spec.channels) {
+        return Err(format!(
+            "FLAC output supports 1 to 8 channels, got {}",
+            spec.channels
+        ));
+    }
+    Ok(())
+}
+
+/// Streaming FLAC encoder. Samples are buffered one block at a time and each
+/// full block is written as a frame straight away; `finalize` patches the
+/// total sample count into the STREAMINFO header.
+pub(crate) struct FlacEncoder<W: Write + Seek> {
+    sink: W,
+    spec: hound::WavSpec,
+    /// Offset of the STREAMINFO block within the sink.
+    stream_info_at: u64,
+    /// Buffered samples of the current block, one vector per channel.
+    block: Vec<Vec<i64>>,
+    frame_number: u64,
+    total_frames: u64,
+}
+
+impl<W: Write + Seek> FlacEncoder<W> {
+    pub(crate) fn new(mut sink: W, spec: hound::WavSpec) -> std::io::Result<Self> {
+        validate_spec(&spec).map_err(std::io::Error::other)?;
+        sink.write_all(b"fLaC")?;
+        let stream_info_at = sink.stream_position()?;
+        let mut encoder = Self {
+            sink,
+            spec,
+            stream_info_at,
+            block: vec![Vec::with_capacity(BLOCK_SIZE); spec.channels as usize],
+            frame_number: 0,
+            total_frames: 0,
+        };
+        encoder.write_stream_info()?;
+        Ok(encoder)
+    }
+
+    pub(crate) fn spec(&self) -> hound::WavSpec {
+        self.spec
+    }
+
+    /// Encodes interleaved normalized samples at the spec's bit depth.
+    pub(crate) fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        let bits = self.spec.bits_per_sample;
+        for frame in samples.chunks_exact(self.block.len()) {
+            for (channel, &value) in self.block.iter_mut().zip(frame) {
+                channel.push(sample::quantize(value, bits) as i64);
+            }
+            if self.block[0].len() == BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finalize(mut self) -> std::io::Result<()> {
+        if !self.block[0].is_empty() {
+            self.write_frame()?;
+        }
+        let end = self.sink.stream_position()?;
+        self.sink.seek(SeekFrom::Start(self.stream_info_at))?;
+        self.write_stream_info()?;
+        self.sink.seek(SeekFrom::Start(end))?;
+        self.sink.flush()
+    }
+
+    fn write_stream_info(&mut self) -> std::io::Result<()> {
+        let mut bits = BitWriter::new();
+        // Last metadata block, type STREAMINFO, 34 bytes.
+        bits.write(1, 1);
+        bits.write(0, 7);
+        bits.write(34, 24);
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(BLOCK_SIZE as u64, 16);
+        // Minimum and maximum frame sizes are left unknown.
+        bits.write(0, 24);
+        bits.write(0, 24);
+        bits.write(self.spec.sample_rate as u64, 20);
+        bits.write(self.spec.channels as u64 - 1, 3);
+        bits.write(self.spec.bits_per_sample as u64 - 1, 5);
+        bits.write(self.total_frames, 36);
+        // MD5 of the audio, all zero meaning "not computed".
+        bits.write(0, 64);
+        bits.write(0, 64);
+        self.sink.write_all(&bits.bytes)
+    }
+
+    fn write_frame(&mut self) -> std::io::Result<()> {
+        let block_size = self.block[0].len();
+        let bits_per_sample = self.spec.bits_per_sample as u32;
+        let (rate_code, rate_extra) = sample_rate_code(self.spec.sample_rate);
+
+        let mut bits = BitWriter::new();
+        // Sync code, reserved bit, fixed block size strategy.
+        bits.write(0b11_1111_1111_1110, 14);
+        bits.write(0, 1);
+        bits.write(0, 1);
+        // Block size stored as a 16-bit value after the frame number.
+        bits.write(0b0111, 4);
+        bits.write(rate_code, 4);
+        // Independent channels.
+        bits.write(self.spec.channels as u64 - 1, 4);
+        bits.write(if bits_per_sample == 16 { 0b100 } else { 0b110 }, 3);
+        bits.write(0, 1);
+        write_coded_number(&mut bits, self.frame_number);
+        bits.write(block_size as u64 - 1, 16);
+        if let Some((value, width)) = rate_extra {
+            bits.write(value, width);
+        }
+        let crc = crc8(&bits.bytes);
+        bits.write(crc as u64, 8);
+
+        for channel in &self.block {
+            write_subframe(&mut bits, channel, bits_per_sample);
+        }
+        bits.align();
+        let crc = crc16(&bits.bytes);
+        bits.write(crc as u64, 16);
+
+        self.sink.write_all(&bits.bytes)?;
+        self.frame_number += 1;
+        self.total_frames += block_size as u64;
+        for channel in &mut self.block {
+            channel.clear();
+        }
+        Ok(())
+    }
+}
+
+/// Frame header sample rate code, plus the trailing field some codes need.
+fn sample_rate_code(rate: u32) -> (u64, Option<(u64, u32)>) {
+    match rate {
+        88_200 => (0b0001, None),
+        176_400 => (0b0010, None),
+        192_000 => (0b0011, None),
+        8_000 => (0b0100, None),
+        16_000 => (0b0101, None),
+        22_050 => (0b0110, None),
+        24_000 => (0b0111, None),
+        32_000 => (0b1000, None),
+        44_100 => (0b1001, None),
+        48_000 => (0b1010, None),
+        96_000 => (0b1011, None),
+        rate if rate % 1000 == 0 && rate / 1000 < 256 => (0b1100, Some(((rate / 1000) as u64, 8))),
+        rate if rate < 65_536 => (0b1101, Some((rate as u64, 16))),
+        rate if rate % 10 == 0 && rate / 10 < 65_536 => (0b1110, Some(((rate / 10) as u64, 16))),
+        // Taken from STREAMINFO.
+        _ => (0b0000, None),
+    }
+}
+
+/// Writes a frame number in FLAC's UTF-8-like variable-length coding.
+fn write_coded_number(bits: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        bits.write(value, 8);
+        return;
+    }
+    let continuation_bytes = match value {
+        v if v < 0x800 => 1,
+        v if v < 0x1_0000 => 2,
+        v if v < 0x20_0000 => 3,
+        v if v < 0x400_0000 => 4,
+        v if v < 0x8000_0000 => 5,
+        _ => 6,
+    };
+    let lead_marker = (0xFF00u64 >> (continuation_bytes + 1)) & 0xFF;
+    bits.write(lead_marker | (value >> (6 * continuation_bytes)), 8);
+    for index in (0..continuation_bytes).rev() {
+        bits.write(0x80 | ((value >> (6 * index)) & 0x3F), 8);
+    }
+}
+
+/// Residual of the fixed polynomial predictor of `order` at `index`.
+fn fixed_residual(samples: &[i64], order: usize, index: usize) -> i64 {
+    let s = |offset: usize| samples[index - offset];
+    match order {
+        0 => s(0),
+        1 => s(0) - s(1),
+        2 => s(0) - 2 * s(1) + s(2),
+        3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+        _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Bits needed to Rice-code `residuals` with parameter `k`.
+fn rice_bits(residuals: &[u64], k: u32) -> u64 {
+    residuals.iter().map(|&u| (u >> k) + 1 + k as u64).sum()
+}
+
+/// Writes one channel of a frame, using the cheapest fixed predictor or a
+/// verbatim subframe when prediction does not pay off.
+fn write_subframe(bits: &mut BitWriter, samples: &[i64], bits_per_sample: u32) {
+    if samples.iter().all(|&s| s == samples[0]) {
+        bits.write(0b0000_0000, 8);
+        bits.write_signed(samples[0], bits_per_sample);
+        return;
+    }
+
+    let verbatim_bits = samples.len() as u64 * bits_per_sample as u64;
+    let mut best: Option<(u64, usize, u32, Vec<u64>)> = None;
+    for order in 0..=MAX_FIXED_ORDER.min(samples.len() - 1) {
+        let residuals: Vec<u64> = (order..samples.len())
+            .map(|index| zigzag(fixed_residual(samples, order, index)))
+            .collect();
+        let (k, residual_bits) = (0..=MAX_RICE_PARAMETER)
+            .map(|k| (k, rice_bits(&residuals, k)))
+            .min_by_key(|&(_, cost)| cost)
+            .unwrap_or((0, u64::MAX));
+        let cost = order as u64 * bits_per_sample as u64 + 10 + residual_bits;
+        if best
+            .as_ref()
+            .is_none_or(|(best_cost, ..)| cost < *best_cost)
+        {
+            best = Some((cost, order, k, residuals));
+        }
+    }
+
+    match best {
+        Some((cost, order, k, residuals)) if cost < verbatim_bits => {
+            bits.write(0b0001_0000 | ((order as u64) << 1), 8);
+            for &warm_up in &samples[..order] {
+                bits.write_signed(warm_up, bits_per_sample);
+            }
+            // Rice coding with 4-bit parameters, a single partition.
+            bits.write(0b00, 2);
+            bits.write(0, 4);
+            bits.write(k as u64, 4);
+            for u in residuals {
+                bits.write_unary(u >> k);
+                bits.write(u & ((1u64 << k) - 1), k);
+            }
+        }
+        _ => {
+            bits.write(0b0000_0010, 8);
+            for &sample in samples {
+                bits.write_signed(sample, bits_per_sample);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn spec(channels: u16, bits_per_sample: u16, sample_rate: u32) -> hound::WavSpec {
+        hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample,
+            sample_format: hound::SampleFormat::Int,
+        }
+    }
+
+    /// A different tone per channel, with stretches of silence, noise and
+    /// full scale so every subframe type and predictor order gets used.
+    fn signal(channels: u16, frames: usize) -> Vec<f32> {
+        let mut seed = 0x2545_f491_u32;
+        let mut samples = Vec::with_capacity(frames * channels as usize);
+        for frame in 0..frames {
+            for channel in 0..channels {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let noise = (seed >> 8) as f32 / (1 << 24) as f32 - 0.5;
+                let tone = (frame as f32 * 0.01 * (channel + 1) as f32).sin() * 0.6;
+                samples.push(match frame / 1000 {
+                    2 => 0.0,
+                    5 => noise,
+                    7 => 1.0,
+                    _ => tone + noise * 0.01,
+                });
+            }
+        }
+        samples
+    }
+
+    fn encode(spec: hound::WavSpec, samples: &[f32]) -> Vec<u8> {
+        let mut sink = Cursor::new(Vec::new());
+        let mut encoder = FlacEncoder::new(&mut sink, spec).unwrap();
+        // Uneven writes, so blocks straddle calls.
+        for chunk in samples.chunks(999 * spec.channels as usize) {
+            encoder.write(chunk).unwrap();
+        }
+        encoder.finalize().unwrap();
+        sink.into_inner()
+    }
+
+    fn decode(bytes: Vec<u8>) -> (claxon::metadata::StreamInfo, Vec<i32>) {
+        let mut reader = claxon::FlacReader::new(Cursor::new(bytes)).unwrap();
+        let samples = reader.samples().collect::<Result<_, _>>().unwrap();
+        (reader.streaminfo(), samples)
+    }
+
+    #[test]
+    fn round_trips_every_channel_count_and_bit_depth() {
+        let frames = BLOCK_SIZE * 2 + 123;
+        for bits in [16, 24] {
+            for channels in 1..=8 {
+                let samples = signal(channels, frames);
+                let (info, decoded) = decode(encode(spec(channels, bits, 48_000), &samples));
+
+                assert_eq!(info.channels, channels as u32);
+                assert_eq!(info.bits_per_sample, bits as u32);
+                assert_eq!(info.sample_rate, 48_000);
+                assert_eq!(info.samples, Some(frames as u64));
+                let expected: Vec<i32> = samples
+                    .iter()
+                    .map(|&sample| sample::quantize(sample, bits))
+                    .collect();
+                assert!(
+                    decoded == expected,
+                    "{} channels at {} bits did not round-trip",
+                    channels,
+                    bits
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_sample_rates_without_a_header_code() {
+        for rate in [
+            8_000, 11_025, 16_000, 22_050, 44_100, 96_000, 12_345, 50_000,
+        ] {
+            let samples = signal(1, 3000);
+            let (info, decoded) = decode(encode(spec(1, 16, rate), &samples));
+            assert_eq!(info.sample_rate, rate);
+            assert_eq!(decoded.len(), samples.len());
+        }
+    }
+
+    #[test]
+    fn empty_stream_is_valid() {
+        // A total of zero reads back as "unknown", which is what it means
+        // in STREAMINFO.
+        let (info, decoded) = decode(encode(spec(2, 16, 48_000), &[]));
+        assert_eq!(info.samples, None);
+        assert!(decoded.is_empty());
+    }
+
+    #[test]
+    fn rejects_unsupported_specs() {
+        assert!(validate_spec(&spec(2, 32, 48_000)).is_err());
+        assert!(validate_spec(&spec(9, 16, 48_000)).is_err());
+        assert!(validate_spec(&hound::WavSpec {
+            sample_format: hound::SampleFormat::Float,
+            ..spec(2, 32, 48_000)
+        })
+        .is_err());
+        assert!(FlacEncoder::new(Cursor::new(Vec::new()), spec(0, 16, 48_000)).is_err());
+    }
+}
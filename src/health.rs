@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+/// Normalized level treated as full scale. Integer inputs never quite reach
+/// 1.0 on the positive side.
+const FULL_SCALE: f32 = 0.999;
+
+/// Thresholds for clipping and dead-input detection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputHealthConfig {
+    /// Consecutive full-scale samples on one channel that count as clipping.
+    pub clip_run: u32,
+    /// Samples at or below this many dBFS count as dead input.
+    pub dead_threshold_dbfs: f32,
+    /// How long every channel must stay dead before a warning is raised,
+    /// e.g. because a hardware mute switch is on.
+    pub dead_after: Duration,
+}
+
+impl Default for InputHealthConfig {
+    fn default() -> Self {
+        Self {
+            clip_run: 3,
+            dead_threshold_dbfs: -90.0,
+            dead_after: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputWarning {
+    /// The input hit full scale; the gain is probably too high.
+    Clipping,
+    /// The input has been silent for `InputHealthConfig::dead_after`.
+    DeadInput,
+}
+
+/// Counts reported when a recording stops.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputStats {
+    /// Runs of at least `clip_run` consecutive full-scale samples.
+    pub clipped_runs: u64,
+    pub full_scale_samples: u64,
+    /// Stretches of dead input lasting at least `dead_after`.
+    pub dead_stretches: u64,
+}
+
+/// Watches the samples of one recording for clipping and dead input.
+pub(crate) struct InputMonitor {
+    channels: usize,
+    clip_run: u32,
+    dead_threshold: f32,
+    dead_after_frames: u64,
+    /// Minimum spacing between clipping warnings, so a hot signal does not
+    /// flood subscribers.
+    warning_interval_frames: u64,
+    /// Current run of full-scale samples per channel.
+    runs: Vec<u32>,
+    dead_frames: u64,
+    frame: u64,
+    last_clip_warning: Option<u64>,
+    stats: InputStats,
+}
+
+impl InputMonitor {
+    pub(crate) fn new(config: &InputHealthConfig, channels: u16, sample_rate: u32) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            channels,
+            clip_run: config.clip_run.max(1),
+            dead_threshold: 10f32.powf(config.dead_threshold_dbfs / 20.0),
+            dead_after_frames: (config.dead_after.as_secs_f64() * sample_rate as f64).round()
+                as u64,
+            warning_interval_frames: sample_rate as u64,
+            runs: vec![0; channels],
+            dead_frames: 0,
+            frame: 0,
+            last_clip_warning: None,
+            stats: InputStats::default(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> InputStats {
+        self.stats
+    }
+
+    /// Feeds one block of interleaved samples and passes each warning that
+    /// is due to `warn`. A block can raise both kinds.
+    pub(crate) fn process(&mut self, samples: &[f32], mut warn: impl FnMut(InputWarning)) {
+        let mut clipped = false;
+        let mut dead = false;
+        for frame in samples.chunks_exact(self.channels) {
+            let mut frame_dead = true;
+            for (run, &sample) in self.runs.iter_mut().zip(frame) {
+                let level = sample.abs();
+                if level >= FULL_SCALE {
+                    self.stats.full_scale_samples += 1;
+                    *run += 1;
+                    if *run == self.clip_run {
+                        self.stats.clipped_runs += 1;
+                        clipped = true;
+                    }
+                } else {
+                    *run = 0;
+                }
+                frame_dead &= level <= self.dead_threshold;
+            }
+
+            self.dead_frames = if frame_dead { self.dead_frames + 1 } else { 0 };
+            if self.dead_frames == self.dead_after_frames.max(1) {
+                self.stats.dead_stretches += 1;
+                dead = true;
+            }
+            self.frame += 1;
+        }
+
+        let clip_warning_due = self
+            .last_clip_warning
+            .is_none_or(|at| self.frame - at >= self.warning_interval_frames);
+        if clipped && clip_warning_due {
+            self.last_clip_warning = Some(self.frame);
+            warn(InputWarning::Clipping);
+        }
+        if dead {
+            warn(InputWarning::DeadInput);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    fn monitor(channels: u16) -> InputMonitor {
+        InputMonitor::new(
+            &InputHealthConfig {
+                clip_run: 3,
+                dead_threshold_dbfs: -90.0,
+                dead_after: Duration::from_millis(100),
+            },
+            channels,
+            RATE,
+        )
+    }
+
+    fn warnings(monitor: &mut InputMonitor, samples: &[f32]) -> Vec<InputWarning> {
+        let mut warnings = Vec::new();
+        monitor.process(samples, |warning| warnings.push(warning));
+        warnings
+    }
+
+    #[test]
+    fn counts_runs_of_full_scale_samples_per_channel() {
+        let mut monitor = monitor(2);
+        // Left: a run of two, then a run of four split across blocks.
+        // Right: one full-scale sample per frame, never a run.
+        let block = [1.0, 0.1, -1.0, -1.0, 0.5, 0.1, 1.0, 0.1, 1.0, -1.0];
+        assert_eq!(warnings(&mut monitor, &block), []);
+        assert_eq!(
+            warnings(&mut monitor, &[1.0, 0.1, -1.0, 0.1]),
+            [InputWarning::Clipping]
+        );
+
+        let stats = monitor.stats();
+        assert_eq!(stats.clipped_runs, 1);
+        assert_eq!(stats.full_scale_samples, 8);
+        assert_eq!(stats.dead_stretches, 0);
+    }
+
+    #[test]
+    fn detects_each_dead_stretch_once() {
+        let mut monitor = monitor(2);
+        assert_eq!(warnings(&mut monitor, &[0.0; 2 * 99]), []);
+        assert_eq!(warnings(&mut monitor, &[0.0; 2]), [InputWarning::DeadInput]);
+        assert_eq!(warnings(&mut monitor, &[0.0; 2 * 500]), []);
+
+        // One live channel is enough to end the stretch.
+        assert_eq!(warnings(&mut monitor, &[0.0, 0.1]), []);
+        assert_eq!(
+            warnings(&mut monitor, &[0.0; 2 * 100]),
+            [InputWarning::DeadInput]
+        );
+        assert_eq!(monitor.stats().dead_stretches, 2);
+    }
+
+    #[test]
+    fn rate_limits_clipping_warnings() {
+        let mut monitor = monitor(1);
+        let clip = [1.0, 1.0, 1.0, 0.5];
+        assert_eq!(warnings(&mut monitor, &clip), [InputWarning::Clipping]);
+        assert_eq!(warnings(&mut monitor, &clip), []);
+        assert_eq!(warnings(&mut monitor, &[0.5; 1000]), []);
+        assert_eq!(warnings(&mut monitor, &clip), [InputWarning::Clipping]);
+        // Every run is still counted.
+        assert_eq!(monitor.stats().clipped_runs, 3);
+    }
+
+    #[test]
+    fn reports_clipping_and_dead_input_in_one_block() {
+        let mut monitor = monitor(1);
+        let mut block = vec![1.0; 3];
+        block.extend([0.0; 100]);
+        assert_eq!(
+            warnings(&mut monitor, &block),
+            [InputWarning::Clipping, InputWarning::DeadInput]
+        );
+        let stats = monitor.stats();
+        assert_eq!((stats.clipped_runs, stats.dead_stretches), (1, 1));
+    }
+}
@@ -0,0 +1,21 @@
+pub mod backend;
+mod capture;
+pub mod channels;
+pub mod chunks;
+pub mod encoder;
+pub mod events;
+mod flac;
+pub mod health;
+pub mod meter;
+pub mod opus;
+mod preroll;
+pub mod reconnect;
+pub mod recorder;
+pub mod resample;
+pub mod sample;
+pub mod segment;
+pub mod sink;
+pub mod stream_config;
+pub mod thread;
+pub mod trim;
+pub mod vad;
@@ -1,11 +1,34 @@
-mod recorder;
-mod thread;
-use recorder::{
-    cancel_recording, close_recording_session, close_thread, enumerate_recording_devices,
-    init_recording_session, start_recording, stop_recording,
-};
-use thread::UserRecordingSessionConfig;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{debug, error, info, warn, Level};
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::backend::{
+    AudioBackend, CpalBackend, SyntheticBackend,
+};
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::channels::ChannelPolicy;
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::encoder::OutputFormat;
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::events::RecorderEvent;
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::meter::MeterReading;
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::opus::{
+    OpusApplication, OpusConfig,
+};
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::reconnect::ReconnectConfig;
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::recorder::{
+    DeviceInfo, Recorder,
+};
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::resample::ResampleQuality;
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::segment::{
+    segment_path, SegmentLimit,
+};
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::sink::{
+    RecordingOutput, RecordingTarget,
+};
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::thread::{
+    RecorderStatus, RecordingOptions, RecordingState, UserRecordingSessionConfig,
+};
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::trim::TrimConfig;
 
 fn parse_command(input: &str) -> Vec<String> {
     let mut args = Vec::new();
@@ -50,6 +73,459 @@ fn parse_command(input: &str) -> Vec<String> {
     args
 }
 
+const COMMANDS: &[(&str, &str)] = &[
+    ("devices", "List available recording devices"),
+    (
+        "init [device_name|device_id] [bits_per_sample] [int|float] [options]",
+        "Initialize the audio stream",
+    ),
+    ("destroy", "Destroy the audio stream"),
+    (
+        "start [id] [options]",
+        "Start recording. Optional id for filename [id].wav, .flac or .opus (default: output)",
+    ),
+    (
+        "arm [id] [options]",
+        "Like start, but writing begins once speech is detected",
+    ),
+    (
+        "pause",
+        "Pause recording; the stream stays open and the file continues on resume",
+    ),
+    ("resume", "Resume a paused recording"),
+    ("stop", "Stop recording and save the file"),
+    ("cancel", "Cancel recording without saving"),
+    ("status", "Show the session and recording state"),
+    ("meter", "Show live input levels until Enter is pressed"),
+    ("exit", "Exit the program"),
+];
+
+const INIT_OPTIONS: &[(&str, &str)] = &[
+    (
+        "rate=<hz>",
+        "Resample the output to this sample rate, e.g. rate=16000",
+    ),
+    (
+        "quality=fast|high",
+        "Resampling quality: linear or windowed sinc (default: fast)",
+    ),
+    (
+        "channels=all|mono|<i,j>",
+        "Keep all channels, downmix to mono, or pick channels",
+    ),
+    (
+        "preroll=<seconds>",
+        "Include this much audio from before start, e.g. preroll=0.5",
+    ),
+    (
+        "autostop=<seconds>",
+        "Stop on its own after this much trailing silence",
+    ),
+    (
+        "trim=<dbfs>",
+        "Trim leading/trailing audio quieter than this on stop",
+    ),
+    (
+        "margin=<seconds>",
+        "Silence kept around the trimmed audio (default: 0.2)",
+    ),
+    (
+        "vad=<dbfs>",
+        "Speech threshold for voice detection (default: -40)",
+    ),
+    (
+        "inrate=<hz>",
+        "Ask the device for this capture rate (closest is used)",
+    ),
+    (
+        "inchannels=<n>",
+        "Ask the device for this many input channels",
+    ),
+    (
+        "informat=i8|i16|i32|f32",
+        "Ask the device for this sample format",
+    ),
+    (
+        "buffer=<frames>",
+        "Frames per input callback, clamped to the device's range",
+    ),
+    (
+        "reconnect=same|default",
+        "Reopen the lost device, or also fall back to the default",
+    ),
+];
+
+const START_OPTIONS: &[(&str, &str)] = &[
+    (
+        "output=file|memory",
+        "Record into [id].<ext> or into memory (default: file)",
+    ),
+    (
+        "format=wav|flac|opus",
+        "Output format; the file extension follows it (default: wav)",
+    ),
+    ("bitrate=<kbps>", "Opus target bitrate (default: 32)"),
+    (
+        "application=voip|audio",
+        "Opus tuning for speech or for music (default: voip)",
+    ),
+    (
+        "max=<seconds>",
+        "Stop on its own after exactly this much audio",
+    ),
+    (
+        "segment=<seconds>|<n>mb",
+        "Split into numbered files of this length or size",
+    ),
+];
+
+fn print_table(rows: &[(&str, &str)]) {
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, description) in rows {
+        println!("  {:<width$} - {}", name, description, width = width);
+    }
+}
+
+const METER_FLOOR_DBFS: f32 = -60.0;
+const METER_WIDTH: usize = 30;
+
+/// Renders one bar per channel: `#` up to the RMS level and `|` at the peak.
+fn format_meter(reading: &MeterReading) -> String {
+    let position = |dbfs: f32| {
+        let fraction = (1.0 - dbfs / METER_FLOOR_DBFS).clamp(0.0, 1.0);
+        (fraction * METER_WIDTH as f32).round() as usize
+    };
+    reading
+        .rms_dbfs
+        .iter()
+        .zip(&reading.peak_dbfs)
+        .map(|(&rms, &peak)| {
+            let (rms_end, peak_at) = (position(rms), position(peak));
+            let bar: String = (0..METER_WIDTH)
+                .map(|i| match i {
+                    i if i < rms_end => '#',
+                    i if i + 1 == peak_at => '|',
+                    _ => ' ',
+                })
+                .collect();
+            format!("[{}] {:>6.1} dB", bar, peak.max(METER_FLOOR_DBFS))
+        })
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+fn format_buffer_size(buffer_size: &cpal::SupportedBufferSize) -> String {
+    match buffer_size {
+        cpal::SupportedBufferSize::Range { min, max } if min == max => {
+            format!("buffer {} frames", min)
+        }
+        cpal::SupportedBufferSize::Range { min, max } => format!("buffer {}-{} frames", min, max),
+        cpal::SupportedBufferSize::Unknown => "buffer size unknown".to_string(),
+    }
+}
+
+fn print_device(device: &DeviceInfo) {
+    let default = if device.is_default { " [default]" } else { "" };
+    println!("  - {} (ID: {}){}", device.label, device.device_id, default);
+    match &device.default_config {
+        Some(config) => println!(
+            "      default: {} ch, {} Hz, {:?}, {}",
+            config.channels(),
+            config.sample_rate().0,
+            config.sample_format(),
+            format_buffer_size(config.buffer_size())
+        ),
+        None => println!("      default: unavailable"),
+    }
+    for range in &device.supported_configs {
+        let rates = if range.min_sample_rate() == range.max_sample_rate() {
+            format!("{} Hz", range.min_sample_rate().0)
+        } else {
+            format!(
+                "{}-{} Hz",
+                range.min_sample_rate().0,
+                range.max_sample_rate().0
+            )
+        };
+        println!(
+            "      supports: {} ch, {}, {:?}, {}",
+            range.channels(),
+            rates,
+            range.sample_format(),
+            format_buffer_size(range.buffer_size())
+        );
+    }
+}
+
+fn print_status(status: &RecorderStatus) {
+    println!("State: {:?}", status.state);
+    if let Some(device_name) = &status.device_name {
+        match &status.device_id {
+            Some(device_id) => println!("Device: {} (ID: {})", device_name, device_id),
+            None => println!("Device: {}", device_name),
+        }
+    }
+    if let Some(spec) = &status.spec {
+        println!(
+            "Format: {} ch, {} Hz, {}-bit {:?}",
+            spec.channels, spec.sample_rate, spec.bits_per_sample, spec.sample_format
+        );
+        println!(
+            "Recorded: {} frames ({:.2} s)",
+            status.recorded_frames,
+            status.recorded_frames as f64 / spec.sample_rate as f64
+        );
+    }
+    match &status.output {
+        Some(RecordingOutput::File(filename)) => println!("File: {}", filename),
+        Some(RecordingOutput::Segments(filename)) => {
+            println!("File: {} (segmented)", segment_path(filename, 1))
+        }
+        Some(RecordingOutput::Memory) => println!("File: (in memory)"),
+        Some(RecordingOutput::Sink) => println!("File: (caller-supplied sink)"),
+        None => {}
+    }
+    if let Some(elapsed) = status.session_started_at.and_then(|t| t.elapsed().ok()) {
+        println!("Session started {:.1} s ago", elapsed.as_secs_f64());
+    }
+}
+
+/// Splits arguments into positional ones and `key=value` options.
+fn split_options(args: &[String]) -> (Vec<&str>, Vec<(&str, &str)>) {
+    let mut positional = Vec::new();
+    let mut options = Vec::new();
+    for arg in args {
+        match arg.split_once('=') {
+            Some((key, value)) => options.push((key, value)),
+            None => positional.push(arg.as_str()),
+        }
+    }
+    (positional, options)
+}
+
+fn apply_init_option(
+    config: &mut UserRecordingSessionConfig,
+    key: &str,
+    value: &str,
+) -> Result<(), String> {
+    match key {
+        "rate" => {
+            let rate = value
+                .parse::<u32>()
+                .ok()
+                .filter(|&rate| rate > 0)
+                .ok_or_else(|| format!("Invalid sample rate: {}", value))?;
+            config.target_sample_rate = Some(rate);
+        }
+        "quality" => {
+            config.resample_quality = match value {
+                "fast" => ResampleQuality::Fast,
+                "high" => ResampleQuality::High,
+                _ => return Err(format!("quality must be fast or high, got {}", value)),
+            };
+        }
+        "channels" => {
+            config.channel_policy = match value {
+                "all" => ChannelPolicy::KeepAll,
+                "mono" => ChannelPolicy::DownmixMono,
+                indices => ChannelPolicy::Select(
+                    indices
+                        .split(',')
+                        .map(|i| i.parse::<u16>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| {
+                            format!("channels must be all, mono or e.g. 0,1, got {}", value)
+                        })?,
+                ),
+            };
+        }
+        "preroll" => {
+            let seconds = value
+                .parse::<f64>()
+                .ok()
+                .filter(|&seconds| (0.0..=60.0).contains(&seconds))
+                .ok_or_else(|| format!("Invalid pre-roll duration: {}", value))?;
+            config.pre_roll = Duration::from_secs_f64(seconds);
+        }
+        "autostop" => {
+            let seconds = value
+                .parse::<f64>()
+                .ok()
+                .filter(|&seconds| seconds > 0.0 && seconds.is_finite())
+                .ok_or_else(|| format!("Invalid auto-stop duration: {}", value))?;
+            config.auto_stop_after = Some(Duration::from_secs_f64(seconds));
+        }
+        "trim" => {
+            config
+                .trim_silence
+                .get_or_insert_with(TrimConfig::default)
+                .threshold_dbfs = value
+                .parse::<f32>()
+                .ok()
+                .filter(|&dbfs| dbfs <= 0.0)
+                .ok_or_else(|| format!("Invalid trim threshold (dBFS): {}", value))?;
+        }
+        "margin" => {
+            let seconds = value
+                .parse::<f64>()
+                .ok()
+                .filter(|&seconds| (0.0..=60.0).contains(&seconds))
+                .ok_or_else(|| format!("Invalid trim margin: {}", value))?;
+            config
+                .trim_silence
+                .get_or_insert_with(TrimConfig::default)
+                .margin = Duration::from_secs_f64(seconds);
+        }
+        "vad" => {
+            config.vad.threshold_dbfs = value
+                .parse::<f32>()
+                .ok()
+                .filter(|&dbfs| dbfs <= 0.0)
+                .ok_or_else(|| format!("Invalid speech threshold (dBFS): {}", value))?;
+        }
+        "inrate" => {
+            config.stream.sample_rate = Some(
+                value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&rate| rate > 0)
+                    .ok_or_else(|| format!("Invalid capture rate: {}", value))?,
+            );
+        }
+        "inchannels" => {
+            config.stream.channels = Some(
+                value
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|&channels| channels > 0)
+                    .ok_or_else(|| format!("Invalid input channel count: {}", value))?,
+            );
+        }
+        "informat" => {
+            config.stream.sample_format = Some(match value {
+                "i8" => cpal::SampleFormat::I8,
+                "i16" => cpal::SampleFormat::I16,
+                "i32" => cpal::SampleFormat::I32,
+                "f32" => cpal::SampleFormat::F32,
+                _ => {
+                    return Err(format!(
+                        "informat must be i8, i16, i32 or f32, got {}",
+                        value
+                    ))
+                }
+            });
+        }
+        "buffer" => {
+            config.stream.buffer_size = cpal::BufferSize::Fixed(
+                value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&frames| frames > 0)
+                    .ok_or_else(|| format!("Invalid buffer size: {}", value))?,
+            );
+        }
+        "reconnect" => {
+            config.reconnect = Some(ReconnectConfig {
+                fallback_to_default: match value {
+                    "same" => false,
+                    "default" => true,
+                    _ => return Err(format!("reconnect must be same or default, got {}", value)),
+                },
+                ..ReconnectConfig::default()
+            });
+        }
+        _ => return Err(format!("Unknown option: {}", key)),
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct StartOptions {
+    in_memory: bool,
+    recording: RecordingOptions,
+}
+
+fn parse_start_options(options: &[(&str, &str)]) -> Result<StartOptions, String> {
+    let mut start_options = StartOptions::default();
+    let mut opus = None::<OpusConfig>;
+    for &(key, value) in options {
+        match (key, value) {
+            ("output", "file") => start_options.in_memory = false,
+            ("output", "memory") => start_options.in_memory = true,
+            ("output", _) => return Err(format!("output must be file or memory, got {}", value)),
+            ("format", "wav") => start_options.recording.format = OutputFormat::Wav,
+            ("format", "flac") => start_options.recording.format = OutputFormat::Flac,
+            ("format", "opus") => {
+                start_options.recording.format = OutputFormat::Opus(opus.unwrap_or_default())
+            }
+            ("format", _) => {
+                return Err(format!("format must be wav, flac or opus, got {}", value))
+            }
+            ("bitrate", _) => {
+                let kbps = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|&kbps| kbps > 0.0 && kbps.is_finite())
+                    .ok_or_else(|| format!("Invalid bitrate: {}", value))?;
+                opus.get_or_insert_with(OpusConfig::default).bitrate = (kbps * 1000.0) as u32;
+            }
+            ("application", "voip") => {
+                opus.get_or_insert_with(OpusConfig::default).application = OpusApplication::Voip
+            }
+            ("application", "audio") => {
+                opus.get_or_insert_with(OpusConfig::default).application = OpusApplication::Audio
+            }
+            ("application", _) => {
+                return Err(format!("application must be voip or audio, got {}", value))
+            }
+            ("max", _) => {
+                let seconds = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|&seconds| seconds > 0.0 && seconds.is_finite())
+                    .ok_or_else(|| format!("Invalid maximum duration: {}", value))?;
+                start_options.recording.max_duration = Some(Duration::from_secs_f64(seconds));
+            }
+            ("segment", _) => {
+                let limit = match value.strip_suffix("mb") {
+                    Some(megabytes) => megabytes
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|&megabytes| megabytes > 0.0)
+                        .map(|megabytes| SegmentLimit::Bytes((megabytes * 1_000_000.0) as u64)),
+                    None => value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|&seconds| seconds > 0.0 && seconds.is_finite())
+                        .map(|seconds| SegmentLimit::Duration(Duration::from_secs_f64(seconds))),
+                };
+                start_options.recording.segment = Some(limit.ok_or_else(|| {
+                    format!("segment must be e.g. 300 (seconds) or 50mb, got {}", value)
+                })?);
+            }
+            _ => return Err(format!("Unknown option: {}", key)),
+        }
+    }
+    match (&mut start_options.recording.format, opus) {
+        (OutputFormat::Opus(config), Some(opus)) => *config = opus,
+        (_, Some(_)) => return Err("bitrate and application need format=opus".to_string()),
+        _ => {}
+    }
+    Ok(start_options)
+}
+
+/// Picks the audio backend for the REPL. Setting `AUDIO_BACKEND=synthetic`
+/// swaps the sound card for generated test signals.
+fn select_backend() -> Box<dyn AudioBackend + Send> {
+    match std::env::var("AUDIO_BACKEND").as_deref() {
+        Ok("synthetic") => {
+            info!("Using synthetic audio backend");
+            Box::new(SyntheticBackend::default())
+        }
+        _ => Box::new(CpalBackend::default()),
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize logging with environment variable control
     // Set RUST_LOG=debug for debug output, info by default
@@ -60,17 +536,33 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .init();
 
     info!("Starting Audio Recorder CLI");
+    let recorder = Recorder::with_backend(select_backend())?;
+
+    // Stream errors and other unsolicited events arrive on their own channel.
+    let events = recorder.subscribe();
+    std::thread::spawn(move || {
+        for event in events {
+            // Only failures go to the log; everything else is just shown.
+            if matches!(
+                event,
+                RecorderEvent::StreamError(_)
+                    | RecorderEvent::DeviceLost
+                    | RecorderEvent::ReconnectFailed { .. }
+            ) {
+                warn!("Recorder event: {:?}", event);
+            }
+            println!("Event: {:?}", event);
+        }
+    });
     debug!("Initializing command interface");
 
     println!("Audio Recorder CLI");
     println!("Available commands:");
-    println!("  devices                              - List available recording devices");
-    println!("  init [device_name] [bits_per_sample] - Initialize the audio stream");
-    println!("  destroy                              - Destroy the audio stream");
-    println!("  start [id]                           - Start recording. Optional id for filename [id].wav (default: output)");
-    println!("  stop                                 - Stop recording and save the file");
-    println!("  cancel                               - Cancel recording without saving");
-    println!("  exit                                 - Exit the program");
+    print_table(COMMANDS);
+    println!("\nInit options (key=value, after the positional arguments):");
+    print_table(INIT_OPTIONS);
+    println!("\nStart/arm options (key=value, after the id):");
+    print_table(START_OPTIONS);
     println!("\nNote: Use quotes for arguments containing spaces, e.g., init \"My Device\" 32");
 
     loop {
@@ -79,13 +571,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let parts = parse_command(input.trim());
         debug!("Parsed command: {:?}", parts);
 
-        match parts.get(0).map(|s| s.as_str()) {
-            Some("devices") => match enumerate_recording_devices() {
+        match parts.first().map(|s| s.as_str()) {
+            Some("devices") => match recorder.enumerate_recording_devices() {
                 Ok(devices) => {
                     info!("Successfully enumerated {} devices", devices.len());
                     println!("\nAvailable recording devices:");
                     for device in devices {
-                        println!("  - {} (ID: {})", device.label, device.device_id);
+                        print_device(&device);
                     }
                 }
                 Err(e) => {
@@ -94,13 +586,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             },
             Some("init") => {
-                let device_name = parts
-                    .get(1)
+                let (args, options) = split_options(&parts[1..]);
+
+                let device_name = args
+                    .first()
                     .map(|s| s.to_string())
                     .unwrap_or_else(|| "default".to_string());
 
-                let bits_per_sample = parts
-                    .get(2)
+                let bits_per_sample = args
+                    .get(1)
                     .and_then(|s| s.parse::<u16>().ok())
                     .unwrap_or(32);
 
@@ -110,19 +604,56 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     continue;
                 }
 
+                let sample_format = match args.get(2).copied() {
+                    None | Some("int") => hound::SampleFormat::Int,
+                    Some("float") if bits_per_sample == 32 => hound::SampleFormat::Float,
+                    Some("float") => {
+                        error!("Float output requested with {} bits", bits_per_sample);
+                        println!("Error: float output requires 32 bits_per_sample");
+                        continue;
+                    }
+                    Some(other) => {
+                        error!("Invalid sample format: {}", other);
+                        println!("Error: sample format must be int or float");
+                        continue;
+                    }
+                };
+
                 debug!(
-                    "Initializing recording session with device: {}, bits: {}",
-                    device_name, bits_per_sample
+                    "Initializing recording session with device: {}, bits: {}, format: {:?}",
+                    device_name, bits_per_sample, sample_format
                 );
-                let config = UserRecordingSessionConfig {
+                let mut config = UserRecordingSessionConfig {
                     device_name,
                     bits_per_sample,
+                    sample_format,
+                    ..Default::default()
                 };
+                if let Err(e) = options
+                    .iter()
+                    .try_for_each(|(key, value)| apply_init_option(&mut config, key, value))
+                {
+                    error!("Invalid init option: {}", e);
+                    println!("Error: {}", e);
+                    continue;
+                }
 
-                match init_recording_session(config) {
-                    Ok(_) => {
+                match recorder.init_recording_session(config) {
+                    Ok(stream) => {
                         info!("Recording session initialized successfully");
-                        println!("Recording session initialized");
+                        println!(
+                            "Recording session initialized on {}: {} ch, {} Hz, {:?}, {}",
+                            stream.device_id,
+                            stream.channels,
+                            stream.sample_rate,
+                            stream.sample_format,
+                            match stream.buffer_size {
+                                cpal::BufferSize::Fixed(frames) => {
+                                    format!("buffer {} frames", frames)
+                                }
+                                cpal::BufferSize::Default => "default buffer".to_string(),
+                            }
+                        );
                     }
                     Err(e) => {
                         error!("Failed to initialize recording session: {}", e);
@@ -132,7 +663,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             Some("destroy") => {
                 debug!("Attempting to destroy recording session");
-                match close_recording_session() {
+                match recorder.close_recording_session() {
                     Ok(_) => {
                         info!("Recording session destroyed successfully");
                         println!("Recording session destroyed");
@@ -143,14 +674,42 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             }
-            Some("start") => {
-                let id = parts
-                    .get(1)
+            Some(command @ ("start" | "arm")) => {
+                let (args, options) = split_options(&parts[1..]);
+                let id = args
+                    .first()
                     .map(|s| s.to_string())
                     .unwrap_or_else(|| "output".to_string());
 
-                debug!("Starting recording with id: {}", id);
-                match start_recording(id) {
+                let start_options = match parse_start_options(&options) {
+                    Ok(start_options) => start_options,
+                    Err(e) => {
+                        error!("Invalid start option: {}", e);
+                        println!("Error: {}", e);
+                        continue;
+                    }
+                };
+
+                debug!("Starting recording with id: {} ({:?})", id, start_options);
+                let target = if start_options.in_memory {
+                    RecordingTarget::Memory
+                } else {
+                    RecordingTarget::File(format!(
+                        "{}.{}",
+                        id,
+                        start_options.recording.format.extension()
+                    ))
+                };
+                let result = if command == "arm" {
+                    recorder.arm_recording(target, start_options.recording)
+                } else {
+                    recorder.start_recording_with(target, start_options.recording)
+                };
+                match result {
+                    Ok(_) if command == "arm" => {
+                        info!("Recording armed successfully");
+                        println!("Recording armed, waiting for speech");
+                    }
                     Ok(_) => {
                         info!("Recording started successfully");
                         println!("Recording started");
@@ -163,10 +722,27 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             Some("stop") => {
                 debug!("Attempting to stop recording");
-                match stop_recording() {
-                    Ok(wav_data) => {
-                        info!("Recording stopped successfully ({} bytes)", wav_data.len());
-                        println!("Recording stopped and saved ({} bytes)", wav_data.len());
+                match recorder.stop_recording() {
+                    Ok(recording) => {
+                        info!(
+                            "Recording stopped successfully ({} bytes)",
+                            recording.data.len()
+                        );
+                        println!(
+                            "Recording stopped and saved ({} bytes, {})",
+                            recording.data.len(),
+                            recording.mime_type
+                        );
+                        for segment in &recording.segments {
+                            println!("  Segment {}: {}", segment.index, segment.path);
+                        }
+                        let stats = recording.input_stats;
+                        if stats.clipped_runs > 0 || stats.dead_stretches > 0 {
+                            println!(
+                                "Warning: {} clipped runs ({} full-scale samples), {} dead-input stretches",
+                                stats.clipped_runs, stats.full_scale_samples, stats.dead_stretches
+                            );
+                        }
                     }
                     Err(e) => {
                         error!("Failed to stop recording: {}", e);
@@ -174,9 +750,82 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             }
+            Some("pause") => {
+                debug!("Attempting to pause recording");
+                match recorder.pause_recording() {
+                    Ok(_) => {
+                        info!("Recording paused successfully");
+                        println!("Recording paused");
+                    }
+                    Err(e) => {
+                        error!("Failed to pause recording: {}", e);
+                        println!("Error pausing recording: {}", e);
+                    }
+                }
+            }
+            Some("resume") => {
+                debug!("Attempting to resume recording");
+                match recorder.resume_recording() {
+                    Ok(_) => {
+                        info!("Recording resumed successfully");
+                        println!("Recording resumed");
+                    }
+                    Err(e) => {
+                        error!("Failed to resume recording: {}", e);
+                        println!("Error resuming recording: {}", e);
+                    }
+                }
+            }
+            Some("status") => match recorder.get_state() {
+                Ok(status) => {
+                    debug!("Recorder status: {:?}", status);
+                    print_status(&status);
+                }
+                Err(e) => {
+                    error!("Failed to get recorder state: {}", e);
+                    println!("Error getting recorder state: {}", e);
+                }
+            },
+            Some("meter") => {
+                match recorder.get_state() {
+                    Ok(status) if matches!(status.state, RecordingState::Idle) => {
+                        println!("Error: Recording session not initialized");
+                        continue;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Failed to get recorder state: {}", e);
+                        println!("Error getting recorder state: {}", e);
+                        continue;
+                    }
+                }
+
+                let readings = recorder.subscribe_meter();
+                let stop = Arc::new(AtomicBool::new(false));
+                let stop_flag = Arc::clone(&stop);
+                let printer = std::thread::spawn(move || {
+                    while !stop_flag.load(Ordering::Relaxed) {
+                        match readings.recv_timeout(Duration::from_millis(100)) {
+                            Ok(reading) => {
+                                print!("\r{}", format_meter(&reading));
+                                let _ = std::io::stdout().flush();
+                            }
+                            Err(RecvTimeoutError::Timeout) => {}
+                            Err(RecvTimeoutError::Disconnected) => break,
+                        }
+                    }
+                });
+
+                println!("Metering input levels, press Enter to stop");
+                let mut line = String::new();
+                std::io::stdin().read_line(&mut line)?;
+                stop.store(true, Ordering::Relaxed);
+                let _ = printer.join();
+                println!();
+            }
             Some("cancel") => {
                 debug!("Attempting to cancel recording");
-                match cancel_recording() {
+                match recorder.cancel_recording() {
                     Ok(_) => {
                         info!("Recording cancelled successfully");
                         println!("Recording cancelled");
@@ -190,13 +839,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             Some("exit") => {
                 info!("Received exit command");
                 // Try to clean up any active recording session before exiting
-                if let Err(e) = close_recording_session() {
+                if let Err(e) = recorder.close_recording_session() {
                     warn!("Failed to clean up recording session: {}", e);
                     println!("Warning: Failed to clean up recording session: {}", e);
                 }
 
                 // Close the audio thread
-                if let Err(e) = close_thread() {
+                if let Err(e) = recorder.close_thread() {
                     warn!("Failed to close audio thread: {}", e);
                     println!("Warning: Failed to close audio thread: {}", e);
                 }
@@ -207,7 +856,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             _ => {
                 error!("Unknown command received: {:?}", parts);
-                println!("Unknown command. Available commands: devices, init [device_name] [bits_per_sample], destroy, start [id], stop, cancel, exit");
+                let names: Vec<&str> = COMMANDS.iter().map(|(name, _)| *name).collect();
+                println!("Unknown command. Available commands: {}", names.join(", "));
             }
         }
     }
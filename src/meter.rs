@@ -0,0 +1,158 @@
+use crate::events::EventBus;
+
+/// Levels of each input channel over one metering interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterReading {
+    pub rms_dbfs: Vec<f32>,
+    pub peak_dbfs: Vec<f32>,
+}
+
+fn to_dbfs(amplitude: f64) -> f32 {
+    (20.0 * amplitude.log10()) as f32
+}
+
+/// Accumulates per-channel levels block by block and publishes a reading
+/// every `interval_frames`.
+pub(crate) struct Meter {
+    channels: usize,
+    interval_frames: u64,
+    frames: u64,
+    sum_squares: Vec<f64>,
+    peaks: Vec<f32>,
+    bus: EventBus<MeterReading>,
+}
+
+impl Meter {
+    /// `rate_hz` of zero disables metering.
+    pub(crate) fn new(
+        channels: u16,
+        sample_rate: u32,
+        rate_hz: u32,
+        bus: EventBus<MeterReading>,
+    ) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            channels,
+            interval_frames: match rate_hz {
+                0 => 0,
+                rate => (sample_rate / rate).max(1) as u64,
+            },
+            frames: 0,
+            sum_squares: vec![0.0; channels],
+            peaks: vec![0.0; channels],
+            bus,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.interval_frames > 0
+    }
+
+    /// Feeds one block of interleaved, normalized samples.
+    pub(crate) fn process(&mut self, samples: &[f32]) {
+        if !self.is_enabled() {
+            return;
+        }
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                self.sum_squares[channel] += sample as f64 * sample as f64;
+                self.peaks[channel] = self.peaks[channel].max(sample.abs());
+            }
+            self.frames += 1;
+            if self.frames == self.interval_frames {
+                self.publish();
+            }
+        }
+    }
+
+    fn publish(&mut self) {
+        let frames = self.frames as f64;
+        let reading = MeterReading {
+            rms_dbfs: self
+                .sum_squares
+                .iter()
+                .map(|&sum| to_dbfs((sum / frames).sqrt()))
+                .collect(),
+            peak_dbfs: self
+                .peaks
+                .iter()
+                .map(|&peak| to_dbfs(peak as f64))
+                .collect(),
+        };
+        self.bus.emit(reading);
+        self.frames = 0;
+        self.sum_squares.fill(0.0);
+        self.peaks.fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Receiver;
+
+    const RATE: u32 = 1000;
+
+    fn meter(channels: u16, rate_hz: u32) -> (Meter, Receiver<MeterReading>) {
+        let bus = EventBus::default();
+        let readings = bus.subscribe();
+        (Meter::new(channels, RATE, rate_hz, bus), readings)
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 0.01, "{:?} vs {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn reports_rms_and_peak_per_channel() {
+        let (mut meter, readings) = meter(2, 10);
+        // Left: a full-scale sine over whole periods. Right: DC at -0.25.
+        let samples: Vec<f32> = (0..100)
+            .flat_map(|t| {
+                let sine = (t as f32 * std::f32::consts::TAU / 20.0).sin();
+                [sine, -0.25]
+            })
+            .collect();
+        meter.process(&samples);
+
+        let reading = readings.try_recv().unwrap();
+        assert_close(&reading.rms_dbfs, &[-3.01, -12.04]);
+        assert_close(&reading.peak_dbfs, &[0.0, -12.04]);
+        assert!(readings.try_recv().is_err());
+    }
+
+    #[test]
+    fn publishes_every_interval_across_blocks() {
+        let (mut meter, readings) = meter(1, 10);
+        for _ in 0..10 {
+            meter.process(&[0.5; 33]);
+        }
+        // 330 frames at one reading per 100.
+        assert_eq!(readings.try_iter().count(), 3);
+
+        // The partial interval carries over rather than being reset.
+        meter.process(&[0.5; 70]);
+        let reading = readings.try_recv().unwrap();
+        assert_close(&reading.rms_dbfs, &[-6.02]);
+    }
+
+    #[test]
+    fn silence_reads_as_negative_infinity() {
+        let (mut meter, readings) = meter(1, 10);
+        meter.process(&[0.0; 100]);
+        let reading = readings.try_recv().unwrap();
+        assert_eq!(reading.rms_dbfs, [f32::NEG_INFINITY]);
+        assert_eq!(reading.peak_dbfs, [f32::NEG_INFINITY]);
+    }
+
+    #[test]
+    fn zero_rate_disables_metering() {
+        let (mut meter, readings) = meter(1, 0);
+        assert!(!meter.is_enabled());
+        meter.process(&[0.5; 5000]);
+        assert!(readings.try_recv().is_err());
+    }
+}
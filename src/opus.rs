@@ -0,0 +1,325 @@
+#[cfg(feature = "opus")]
+use {
+    audiopus::coder::Encoder,
+    audiopus::{Application, Bitrate, Channels, SampleRate},
+    ogg::writing::{PacketWriteEndInfo, PacketWriter},
+    std::io::Write,
+    std::time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Opus always runs at 48 kHz in Ogg; other rates are resampled to this.
+pub const OPUS_SAMPLE_RATE: u32 = 48_000;
+/// 20 ms, the frame size libopus recommends for most uses.
+#[cfg(feature = "opus")]
+const FRAME_SAMPLES: usize = 960;
+/// Largest packet libopus produces for one frame.
+#[cfg(feature = "opus")]
+const MAX_PACKET_BYTES: usize = 4000;
+
+/// Which libopus tuning to use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpusApplication {
+    /// Favours speech intelligibility.
+    #[default]
+    Voip,
+    /// Favours fidelity for music and mixed content.
+    Audio,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpusConfig {
+    /// Target bitrate in bits per second, 6 000 to 510 000.
+    pub bitrate: u32,
+    pub application: OpusApplication,
+}
+
+impl Default for OpusConfig {
+    fn default() -> Self {
+        Self {
+            bitrate: 32_000,
+            application: OpusApplication::default(),
+        }
+    }
+}
+
+impl OpusConfig {
+    /// Checks the settings against the channels being recorded, and that
+    /// the crate was built with the `opus` feature.
+    pub fn validate(&self, channels: u16) -> Result<(), String> {
+        if cfg!(not(feature = "opus")) {
+            return Err("Opus output needs the `opus` cargo feature".to_string());
+        }
+        if !(6_000..=510_000).contains(&self.bitrate) {
+            return Err(format!(
+                "Opus bitrate must be between 6000 and 510000 bps, got {}",
+                self.bitrate
+            ));
+        }
+        if !(1..=2).contains(&channels) {
+            return Err(format!(
+                "Opus output supports 1 or 2 channels, got {}",
+                channels
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Streaming Ogg Opus encoder (RFC 7845). Input must already be at 48 kHz;
+/// each 20 ms frame is encoded and handed to the Ogg writer as it fills up.
+#[cfg(feature = "opus")]
+pub(crate) struct OpusEncoder<W: Write> {
+    ogg: PacketWriter<W>,
+    serial: u32,
+    encoder: Encoder,
+    config: OpusConfig,
+    channels: u16,
+    input_sample_rate: u32,
+    /// Samples the decoder drops at the start, the encoder's lookahead.
+    pre_skip: u64,
+    /// Interleaved samples of the frame being filled.
+    frame: Vec<f32>,
+    packet: Vec<u8>,
+    /// Per-channel samples handed to the encoder, padding included.
+    encoded_samples: u64,
+    /// Per-channel samples of real input.
+    input_samples: u64,
+}
+
+#[cfg(feature = "opus")]
+impl<W: Write> OpusEncoder<W> {
+    /// Writes the OpusHead and OpusTags headers. `input_sample_rate` is
+    /// recorded in the header for players that want to resample back.
+    pub(crate) fn new(
+        sink: W,
+        channels: u16,
+        input_sample_rate: u32,
+        config: OpusConfig,
+    ) -> std::io::Result<Self> {
+        config.validate(channels).map_err(std::io::Error::other)?;
+        let mut encoder = Encoder::new(
+            SampleRate::Hz48000,
+            if channels == 1 {
+                Channels::Mono
+            } else {
+                Channels::Stereo
+            },
+            match config.application {
+                OpusApplication::Voip => Application::Voip,
+                OpusApplication::Audio => Application::Audio,
+            },
+        )
+        .map_err(std::io::Error::other)?;
+        encoder
+            .set_bitrate(Bitrate::BitsPerSecond(config.bitrate as i32))
+            .map_err(std::io::Error::other)?;
+        let pre_skip = encoder.lookahead().map_err(std::io::Error::other)? as u64;
+
+        let serial = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.subsec_nanos() ^ now.as_secs() as u32);
+        let mut ogg = PacketWriter::new(sink);
+
+        let mut head = b"OpusHead".to_vec();
+        head.push(1);
+        head.push(channels as u8);
+        head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&input_sample_rate.to_le_bytes());
+        // Output gain, then mapping family 0 (mono or stereo).
+        head.extend_from_slice(&0i16.to_le_bytes());
+        head.push(0);
+        ogg.write_packet(head.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+        let vendor = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor.as_bytes());
+        tags.extend_from_slice(&0u32.to_le_bytes());
+        ogg.write_packet(tags.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(Self {
+            ogg,
+            serial,
+            encoder,
+            config,
+            channels,
+            input_sample_rate,
+            pre_skip,
+            frame: Vec::with_capacity(FRAME_SAMPLES * channels as usize),
+            packet: vec![0; MAX_PACKET_BYTES],
+            encoded_samples: 0,
+            input_samples: 0,
+        })
+    }
+
+    /// Starts a new stream into `sink` with the same settings.
+    pub(crate) fn reopen<V: Write>(&self, sink: V) -> std::io::Result<OpusEncoder<V>> {
+        OpusEncoder::new(sink, self.channels, self.input_sample_rate, self.config)
+    }
+
+    pub(crate) fn config(&self) -> OpusConfig {
+        self.config
+    }
+
+    pub(crate) fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Encodes interleaved normalized 48 kHz samples.
+    pub(crate) fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        let frame_len = FRAME_SAMPLES * self.channels as usize;
+        self.input_samples += (samples.len() / self.channels as usize) as u64;
+        let mut samples = samples;
+        while !samples.is_empty() {
+            let take = (frame_len - self.frame.len()).min(samples.len());
+            self.frame.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+            if self.frame.len() == frame_len {
+                self.encode_frame(PacketWriteEndInfo::NormalPacket)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Pads the input so the encoder's lookahead is flushed out, then ends
+    /// the stream with a granule position that trims the padding again.
+    pub(crate) fn finalize(mut self) -> std::io::Result<()> {
+        let frame_len = FRAME_SAMPLES * self.channels as usize;
+        let mut padding = self.pre_skip as usize * self.channels as usize;
+        loop {
+            let take = (frame_len - self.frame.len()).min(padding);
+            self.frame.resize(self.frame.len() + take, 0.0);
+            padding -= take;
+            if padding == 0 {
+                self.frame.resize(frame_len, 0.0);
+                self.encode_frame(PacketWriteEndInfo::EndStream)?;
+                break;
+            }
+            self.encode_frame(PacketWriteEndInfo::NormalPacket)?;
+        }
+        self.ogg.inner_mut().flush()
+    }
+
+    fn encode_frame(&mut self, end: PacketWriteEndInfo) -> std::io::Result<()> {
+        let len = self
+            .encoder
+            .encode_float(&self.frame, &mut self.packet)
+            .map_err(std::io::Error::other)?;
+        self.frame.clear();
+        self.encoded_samples += FRAME_SAMPLES as u64;
+        let granule = match end {
+            PacketWriteEndInfo::EndStream => {
+                self.encoded_samples.min(self.pre_skip + self.input_samples)
+            }
+            _ => self.encoded_samples,
+        };
+        self.ogg
+            .write_packet(self.packet[..len].into(), self.serial, end, granule)
+    }
+}
+
+#[cfg(all(test, feature = "opus"))]
+mod tests {
+    use super::*;
+    use audiopus::coder::Decoder;
+    use audiopus::packet::Packet;
+    use audiopus::MutSignals;
+    use ogg::reading::PacketReader;
+    use std::io::Cursor;
+
+    /// One second of a 440 Hz sine at half scale, the same on every channel.
+    fn sine(channels: u16) -> Vec<f32> {
+        (0..OPUS_SAMPLE_RATE)
+            .flat_map(|t| {
+                let value = 0.5 * (t as f32 * 440.0 * std::f32::consts::TAU / 48_000.0).sin();
+                std::iter::repeat_n(value, channels as usize)
+            })
+            .collect()
+    }
+
+    /// Encodes `samples` in uneven blocks and returns every Ogg packet.
+    fn encode(channels: u16, samples: &[f32]) -> Vec<ogg::Packet> {
+        let mut bytes = Vec::new();
+        let mut encoder =
+            OpusEncoder::new(&mut bytes, channels, 16_000, OpusConfig::default()).unwrap();
+        for block in samples.chunks(777 * channels as usize) {
+            encoder.write(block).unwrap();
+        }
+        encoder.finalize().unwrap();
+
+        let mut reader = PacketReader::new(Cursor::new(bytes));
+        std::iter::from_fn(|| reader.read_packet().unwrap()).collect()
+    }
+
+    #[test]
+    fn writes_the_rfc_7845_headers() {
+        let packets = encode(2, &sine(2));
+
+        let head = &packets[0];
+        assert!(head.first_in_stream() && head.last_in_page());
+        assert_eq!(head.absgp_page(), 0);
+        assert_eq!(head.data.len(), 19);
+        assert_eq!(&head.data[..8], b"OpusHead");
+        assert_eq!(head.data[8], 1);
+        assert_eq!(head.data[9], 2);
+        let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]);
+        assert!(pre_skip > 0);
+        let input_rate = u32::from_le_bytes(head.data[12..16].try_into().unwrap());
+        assert_eq!(input_rate, 16_000);
+        assert_eq!(&head.data[16..], [0, 0, 0]);
+
+        let tags = &packets[1];
+        assert!(tags.last_in_page());
+        assert_eq!(tags.absgp_page(), 0);
+        assert_eq!(&tags.data[..8], b"OpusTags");
+        let vendor_len = u32::from_le_bytes(tags.data[8..12].try_into().unwrap()) as usize;
+        assert_eq!(tags.data.len(), 12 + vendor_len + 4);
+        assert!(tags.data[12..12 + vendor_len].starts_with(env!("CARGO_PKG_NAME").as_bytes()));
+    }
+
+    #[test]
+    fn ends_the_stream_at_the_exact_input_length() {
+        let samples = sine(1);
+        let packets = encode(1, &samples);
+        let pre_skip = u16::from_le_bytes([packets[0].data[10], packets[0].data[11]]) as u64;
+        let audio = &packets[2..];
+
+        let (last, rest) = audio.split_last().unwrap();
+        assert!(last.last_in_stream());
+        assert!(rest.iter().all(|packet| !packet.last_in_stream()));
+        assert_eq!(last.absgp_page(), pre_skip + samples.len() as u64);
+        // Enough 20 ms frames to cover the input and flush the lookahead.
+        let encoded = audio.len() as u64 * FRAME_SAMPLES as u64;
+        assert!(encoded >= pre_skip + samples.len() as u64);
+        assert!(encoded < pre_skip + samples.len() as u64 + FRAME_SAMPLES as u64);
+        let granules: Vec<u64> = rest.iter().map(|packet| packet.absgp_page()).collect();
+        assert!(granules.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn decodes_back_to_the_input() {
+        let samples = sine(1);
+        let packets = encode(1, &samples);
+        let pre_skip = u16::from_le_bytes([packets[0].data[10], packets[0].data[11]]) as usize;
+
+        let mut decoder = Decoder::new(SampleRate::Hz48000, Channels::Mono).unwrap();
+        let mut decoded = Vec::new();
+        let mut frame = vec![0.0f32; FRAME_SAMPLES];
+        for packet in &packets[2..] {
+            let packet = Packet::try_from(&packet.data[..]).unwrap();
+            let output = MutSignals::try_from(&mut frame[..]).unwrap();
+            let len = decoder.decode_float(Some(packet), output, false).unwrap();
+            decoded.extend_from_slice(&frame[..len]);
+        }
+        let decoded = &decoded[pre_skip..pre_skip + samples.len()];
+
+        let rms = |s: &[f32]| (s.iter().map(|x| x * x).sum::<f32>() / s.len() as f32).sqrt();
+        let (input, output) = (rms(&samples[4800..]), rms(&decoded[4800..]));
+        assert!(
+            (output / input - 1.0).abs() < 0.1,
+            "{} vs {}",
+            output,
+            input
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Bounded history of the samples the stream delivered while nothing was
+/// being recorded, so a new recording can begin slightly in the past.
+pub(crate) struct PreRoll {
+    samples: VecDeque<f32>,
+    /// Maximum number of interleaved samples kept, always a whole number of frames.
+    capacity: usize,
+}
+
+impl PreRoll {
+    pub(crate) fn new(duration: Duration, sample_rate: u32, channels: u16) -> Self {
+        let frames = (duration.as_secs_f64() * sample_rate as f64).round() as usize;
+        let capacity = frames * channels.max(1) as usize;
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Appends interleaved frames, dropping the oldest ones beyond capacity.
+    pub(crate) fn push(&mut self, samples: &[f32]) {
+        if samples.len() >= self.capacity {
+            self.samples.clear();
+            self.samples
+                .extend(&samples[samples.len() - self.capacity..]);
+            return;
+        }
+        let excess = (self.samples.len() + samples.len()).saturating_sub(self.capacity);
+        self.samples.drain(..excess);
+        self.samples.extend(samples);
+    }
+
+    /// Maximum number of interleaved samples kept.
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Moves everything buffered into `out`, oldest first, replacing its
+    /// contents. Does not allocate once `out` can hold `capacity` samples.
+    pub(crate) fn drain_into(&mut self, out: &mut Vec<f32>) {
+        out.clear();
+        out.extend(self.samples.drain(..));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drained(pre_roll: &mut PreRoll) -> Vec<f32> {
+        let mut out = vec![9.0];
+        pre_roll.drain_into(&mut out);
+        out
+    }
+
+    #[test]
+    fn capacity_is_a_whole_number_of_frames() {
+        let pre_roll = PreRoll::new(Duration::from_millis(100), 1000, 2);
+        assert_eq!(pre_roll.capacity(), 200);
+        assert!(pre_roll.is_enabled());
+        assert!(!PreRoll::new(Duration::ZERO, 1000, 2).is_enabled());
+    }
+
+    #[test]
+    fn keeps_everything_below_capacity() {
+        let mut pre_roll = PreRoll::new(Duration::from_millis(4), 1000, 1);
+        pre_roll.push(&[1.0, 2.0]);
+        pre_roll.push(&[3.0]);
+        assert_eq!(drained(&mut pre_roll), [1.0, 2.0, 3.0]);
+        assert!(drained(&mut pre_roll).is_empty());
+    }
+
+    #[test]
+    fn drops_the_oldest_frames_when_it_wraps() {
+        let mut pre_roll = PreRoll::new(Duration::from_millis(2), 1000, 2);
+        pre_roll.push(&[1.0, 1.5, 2.0, 2.5]);
+        pre_roll.push(&[3.0, 3.5]);
+        assert_eq!(drained(&mut pre_roll), [2.0, 2.5, 3.0, 3.5]);
+
+        // A block larger than the buffer leaves only its tail.
+        pre_roll.push(&[0.0, 0.0]);
+        pre_roll.push(&[4.0, 4.5, 5.0, 5.5, 6.0, 6.5]);
+        assert_eq!(drained(&mut pre_roll), [5.0, 5.5, 6.0, 6.5]);
+    }
+
+    #[test]
+    fn disabled_buffer_keeps_nothing() {
+        let mut pre_roll = PreRoll::new(Duration::ZERO, 1000, 2);
+        pre_roll.push(&[1.0, 2.0]);
+        assert!(drained(&mut pre_roll).is_empty());
+    }
+}
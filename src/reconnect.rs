@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+/// How the audio thread tries to get the input stream back after the device
+/// disappears.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectConfig {
+    /// Also try the host's default input device when the lost one is not
+    /// back yet.
+    pub fallback_to_default: bool,
+    /// Wait before the first attempt. Doubled after every failed attempt,
+    /// up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Give up after this many attempts. `None` keeps trying until the
+    /// session is closed.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            fallback_to_default: false,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+            max_attempts: Some(10),
+        }
+    }
+}
+
+/// Exponential backoff between reconnect attempts.
+pub(crate) struct Backoff {
+    config: ReconnectConfig,
+    attempts: u32,
+    delay: Duration,
+    next_attempt: Instant,
+}
+
+impl Backoff {
+    pub(crate) fn new(config: &ReconnectConfig) -> Self {
+        Self {
+            config: config.clone(),
+            attempts: 0,
+            delay: config.initial_backoff,
+            next_attempt: Instant::now() + config.initial_backoff,
+        }
+    }
+
+    pub(crate) fn config(&self) -> &ReconnectConfig {
+        &self.config
+    }
+
+    /// Attempts made so far.
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Time left until the next attempt is due.
+    pub(crate) fn wait(&self) -> Duration {
+        self.next_attempt.saturating_duration_since(Instant::now())
+    }
+
+    /// Counts an attempt and returns its number, starting at 1.
+    pub(crate) fn attempt(&mut self) -> u32 {
+        self.attempts += 1;
+        self.attempts
+    }
+
+    /// Schedules the next attempt after a failed one. Returns false once
+    /// `max_attempts` is used up.
+    pub(crate) fn failed(&mut self) -> bool {
+        if self
+            .config
+            .max_attempts
+            .is_some_and(|max| self.attempts >= max)
+        {
+            return false;
+        }
+        self.delay = (self.delay * 2).min(self.config.max_backoff);
+        self.next_attempt = Instant::now() + self.delay;
+        true
+    }
+}
@@ -1,17 +1,21 @@
-use crate::thread::{spawn_audio_thread, AudioCommand, AudioResponse, UserRecordingSessionConfig};
-use once_cell::sync::Lazy;
+use crate::backend::{AudioBackend, CpalBackend};
+use crate::chunks::{ChunkConfig, ChunkSubscribers, PcmChunk};
+use crate::encoder::OutputFormat;
+use crate::events::{EventBus, RecorderEvent};
+use crate::health::InputStats;
+use crate::meter::MeterReading;
+use crate::segment::CompletedSegment;
+use crate::sink::{RecordingOutput, RecordingSink, RecordingTarget};
+use crate::stream_config::NegotiatedStream;
+use crate::thread::{
+    spawn_audio_thread, AudioCommand, AudioReply, AudioRequest, AudioResponse, AudioThreadHandle,
+    RecorderStatus, RecordingOptions, RequestId, StoppedRecording, UserRecordingSessionConfig,
+};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Mutex;
 use thiserror::Error;
 use tracing::{debug, error, info, warn};
 
-// Global static mutex to hold the audio thread sender and state
-static AUDIO_THREAD: Lazy<Mutex<Option<(Sender<AudioCommand>, Receiver<AudioResponse>)>>> =
-    Lazy::new(|| Mutex::new(None));
-
-// Track current recording state
-static CURRENT_RECORDING: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
-
 #[derive(Debug, Error)]
 pub enum RecorderError {
     #[error("Audio thread not initialized")]
@@ -34,191 +38,378 @@ type Result<T> = std::result::Result<T, RecorderError>;
 
 #[derive(Debug)]
 pub struct DeviceInfo {
+    /// Stable ID, `<host>:<name>#<n>`, accepted by `init_recording_session`
+    /// in place of the device name. Tells apart devices with the same label.
     pub device_id: String,
     pub label: String,
+    /// Whether this is the host's default input device.
+    pub is_default: bool,
+    /// The config `init_recording_session` opens the device with, if known.
+    pub default_config: Option<cpal::SupportedStreamConfig>,
+    pub supported_configs: Vec<cpal::SupportedStreamConfigRange>,
 }
 
-fn ensure_thread_initialized() -> Result<()> {
-    debug!("Ensuring thread is initialized...");
-    let mut thread = AUDIO_THREAD
-        .lock()
-        .map_err(|e| RecorderError::LockError(e.to_string()))?;
+/// A finished recording, as returned by `Recorder::stop_recording`.
+#[derive(Debug)]
+pub struct Recording {
+    /// The encoded bytes, WAV unless another format was requested. Empty
+    /// for segmented recordings and recordings written to a caller-supplied
+    /// sink.
+    pub data: Vec<u8>,
+    /// MIME type of `data`, e.g. `audio/ogg` for Opus recordings.
+    pub mime_type: &'static str,
+    /// Clipping and dead-input counts, so the caller can flag a bad take.
+    pub input_stats: InputStats,
+    /// The files a segmented recording was split into, in order. They are
+    /// left on disk for the caller.
+    pub segments: Vec<CompletedSegment>,
+}
 
-    if thread.is_none() {
-        debug!("Thread not initialized, creating new audio thread...");
-        let (response_tx, response_rx) = mpsc::channel();
-        let command_tx =
-            spawn_audio_thread(response_tx).map_err(|e| RecorderError::SendError(e.to_string()))?;
-        *thread = Some((command_tx, response_rx));
-        info!("Audio thread created successfully");
-    } else {
-        debug!("Thread already initialized");
+struct AudioThread {
+    tx: Sender<AudioRequest>,
+    rx: Receiver<AudioReply>,
+    handle: AudioThreadHandle,
+    next_request_id: RequestId,
+}
+
+impl AudioThread {
+    /// Sends `command` and waits for the reply carrying the same request ID.
+    /// Replies to earlier requests that were abandoned are discarded.
+    fn request(&mut self, command: AudioCommand) -> Result<AudioResponse> {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+
+        self.tx
+            .send(AudioRequest { id, command })
+            .map_err(|e| RecorderError::SendError(e.to_string()))?;
+
+        loop {
+            let reply = self
+                .rx
+                .recv()
+                .map_err(|e| RecorderError::ReceiveError(e.to_string()))?;
+            if reply.id == id {
+                return Ok(reply.response);
+            }
+            warn!(
+                "Discarding stale response for request {}: {:?}",
+                reply.id, reply.response
+            );
+        }
     }
-    Ok(())
 }
 
-fn with_thread<F, T>(f: F) -> Result<T>
-where
-    F: FnOnce(&Sender<AudioCommand>, &Receiver<AudioResponse>) -> Result<T>,
-{
-    ensure_thread_initialized()?;
-    let thread = AUDIO_THREAD
-        .lock()
-        .map_err(|e| RecorderError::LockError(e.to_string()))?;
-    let (tx, rx) = thread.as_ref().ok_or(RecorderError::ThreadNotInitialized)?;
-    f(tx, rx)
+/// Owns an audio thread, its command/response channels and the state of the
+/// current recording. Dropping the recorder shuts the thread down.
+pub struct Recorder {
+    thread: Mutex<Option<AudioThread>>,
+    current_recording: Mutex<Option<RecordingOutput>>,
+    events: EventBus,
+    meters: EventBus<MeterReading>,
+    chunks: ChunkSubscribers,
 }
 
-pub fn enumerate_recording_devices() -> Result<Vec<DeviceInfo>> {
-    debug!("Enumerating recording devices");
-    with_thread(|tx, rx| {
-        tx.send(AudioCommand::EnumerateRecordingDevices)
-            .map_err(|e| RecorderError::SendError(e.to_string()))?;
+impl Recorder {
+    /// Creates a recorder backed by the default cpal host.
+    pub fn new() -> Result<Self> {
+        Self::with_backend(Box::new(CpalBackend::default()))
+    }
+
+    pub fn with_backend(backend: Box<dyn AudioBackend + Send>) -> Result<Self> {
+        debug!("Creating new audio thread...");
+        let events = EventBus::default();
+        let meters = EventBus::default();
+        let chunks = ChunkSubscribers::default();
+        let (response_tx, response_rx) = mpsc::channel();
+        let (command_tx, handle) = spawn_audio_thread(
+            backend,
+            response_tx,
+            events.clone(),
+            meters.clone(),
+            chunks.clone(),
+        )?;
+        info!("Audio thread created successfully");
+
+        Ok(Self {
+            thread: Mutex::new(Some(AudioThread {
+                tx: command_tx,
+                rx: response_rx,
+                handle,
+                next_request_id: 1,
+            })),
+            current_recording: Mutex::new(None),
+            events,
+            meters,
+            chunks,
+        })
+    }
+
+    /// Returns a receiver for stream errors, device loss and other events the
+    /// audio thread reports on its own.
+    pub fn subscribe(&self) -> Receiver<RecorderEvent> {
+        self.events.subscribe()
+    }
+
+    /// Returns a receiver for live input levels, published at the session's
+    /// `meter_rate_hz` whenever the stream is running, recording or not.
+    /// Drop the receiver to unsubscribe.
+    pub fn subscribe_meter(&self) -> Receiver<MeterReading> {
+        self.meters.subscribe()
+    }
 
-        match rx.recv() {
-            Ok(AudioResponse::RecordingDeviceList(devices)) => {
+    /// Returns a receiver for live input audio, as fixed-size mono f32
+    /// chunks at `config.sample_rate`, e.g. for a streaming recognizer.
+    /// Chunks are produced after the channel policy whenever the stream is
+    /// running, recording or not, including while paused or armed. A consumer
+    /// that falls behind loses chunks (visible as gaps in `sequence`) rather
+    /// than stalling the WAV. Drop the receiver to unsubscribe.
+    pub fn subscribe_chunks(&self, config: ChunkConfig) -> Receiver<PcmChunk> {
+        self.chunks.subscribe(config)
+    }
+
+    fn request(&self, command: AudioCommand) -> Result<AudioResponse> {
+        let mut thread = self
+            .thread
+            .lock()
+            .map_err(|e| RecorderError::LockError(e.to_string()))?;
+        thread
+            .as_mut()
+            .ok_or(RecorderError::ThreadNotInitialized)?
+            .request(command)
+    }
+
+    fn set_current_recording(&self, output: Option<RecordingOutput>) -> Result<()> {
+        *self
+            .current_recording
+            .lock()
+            .map_err(|e| RecorderError::LockError(e.to_string()))? = output;
+        Ok(())
+    }
+
+    fn current_recording(&self) -> Result<RecordingOutput> {
+        self.current_recording
+            .lock()
+            .map_err(|e| RecorderError::LockError(e.to_string()))?
+            .clone()
+            .ok_or(RecorderError::NoActiveRecording)
+    }
+
+    pub fn enumerate_recording_devices(&self) -> Result<Vec<DeviceInfo>> {
+        debug!("Enumerating recording devices");
+        match self.request(AudioCommand::EnumerateRecordingDevices)? {
+            AudioResponse::RecordingDeviceList(devices) => {
                 info!("Found {} recording devices", devices.len());
                 Ok(devices
                     .into_iter()
-                    .map(|label| DeviceInfo {
-                        device_id: label.clone(),
-                        label,
+                    .map(|device| DeviceInfo {
+                        device_id: device.id.to_string(),
+                        label: device.name,
+                        is_default: device.is_default,
+                        default_config: device.default_config,
+                        supported_configs: device.supported_configs,
                     })
                     .collect())
             }
-            Ok(AudioResponse::Error(e)) => {
+            AudioResponse::Error(e) => {
                 error!("Failed to enumerate devices: {}", e);
                 Err(RecorderError::AudioError(e))
             }
-            Ok(_) => {
+            _ => {
                 error!("Unexpected response while enumerating devices");
                 Err(RecorderError::AudioError("Unexpected response".to_string()))
             }
-            Err(e) => {
-                error!("Failed to receive device enumeration response: {}", e);
-                Err(RecorderError::ReceiveError(e.to_string()))
-            }
         }
-    })
-}
+    }
 
-pub fn init_recording_session(settings: UserRecordingSessionConfig) -> Result<()> {
-    info!(
-        "Starting init_recording_session with settings: {:?}",
-        settings
-    );
-    with_thread(|tx, rx| {
+    /// Opens the input device and reports the stream config it was opened
+    /// with, which may differ from the one requested in `settings.stream`.
+    pub fn init_recording_session(
+        &self,
+        settings: UserRecordingSessionConfig,
+    ) -> Result<NegotiatedStream> {
+        info!(
+            "Starting init_recording_session with settings: {:?}",
+            settings
+        );
         debug!("Sending InitRecordingSession command...");
-        tx.send(AudioCommand::InitRecordingSession(settings))
-            .map_err(|e| RecorderError::SendError(e.to_string()))?;
-
-        debug!("Waiting for response...");
-        match rx.recv() {
-            Ok(AudioResponse::Success(_)) => {
-                info!("Recording session initialized successfully");
-                Ok(())
+        match self.request(AudioCommand::InitRecordingSession(settings))? {
+            AudioResponse::SessionInitialized(stream) => {
+                info!("Recording session initialized successfully: {:?}", stream);
+                Ok(stream)
             }
-            Ok(AudioResponse::Error(e)) => {
+            AudioResponse::Error(e) => {
                 error!("Failed to initialize recording session: {}", e);
                 Err(RecorderError::AudioError(e))
             }
-            Ok(_) => {
+            _ => {
                 error!("Unexpected response during initialization");
                 Err(RecorderError::AudioError("Unexpected response".to_string()))
             }
-            Err(e) => {
-                error!("Failed to receive initialization response: {}", e);
-                Err(RecorderError::ReceiveError(e.to_string()))
-            }
         }
-    })
-}
-
-pub fn close_recording_session() -> Result<()> {
-    with_thread(|tx, rx| {
-        tx.send(AudioCommand::CloseRecordingSession)
-            .map_err(|e| RecorderError::SendError(e.to_string()))?;
+    }
 
-        match rx.recv() {
-            Ok(AudioResponse::Success(_)) => {
-                *CURRENT_RECORDING.lock().unwrap() = None;
-                Ok(())
-            }
-            Ok(AudioResponse::Error(e)) => Err(RecorderError::AudioError(e)),
-            Ok(_) => Err(RecorderError::AudioError("Unexpected response".to_string())),
-            Err(e) => Err(RecorderError::ReceiveError(e.to_string())),
+    /// Closes the input stream. A recording still in progress is finalized
+    /// and, when written to a file, left on disk.
+    pub fn close_recording_session(&self) -> Result<()> {
+        match self.request(AudioCommand::CloseRecordingSession)? {
+            AudioResponse::Success(_) => self.set_current_recording(None),
+            AudioResponse::Error(e) => Err(RecorderError::AudioError(e)),
+            _ => Err(RecorderError::AudioError("Unexpected response".to_string())),
         }
-    })
-}
+    }
 
-pub fn close_thread() -> Result<()> {
-    let mut thread = AUDIO_THREAD
-        .lock()
-        .map_err(|e| RecorderError::LockError(e.to_string()))?;
+    /// Shuts the audio thread down. Later calls fail with `ThreadNotInitialized`.
+    pub fn close_thread(&self) -> Result<()> {
+        let mut thread = self
+            .thread
+            .lock()
+            .map_err(|e| RecorderError::LockError(e.to_string()))?;
 
-    if let Some((tx, rx)) = thread.take() {
-        debug!("Sending CloseThread command...");
-        tx.send(AudioCommand::CloseThread)
-            .map_err(|e| RecorderError::SendError(e.to_string()))?;
+        if let Some(mut thread) = thread.take() {
+            debug!("Sending CloseThread command...");
+            let result = match thread.request(AudioCommand::CloseThread) {
+                Ok(AudioResponse::Success(_)) => {
+                    info!("Audio thread closed successfully");
+                    Ok(())
+                }
+                Ok(AudioResponse::Error(e)) => {
+                    error!("Error closing audio thread: {}", e);
+                    Err(RecorderError::AudioError(e))
+                }
+                Ok(_) => {
+                    error!("Unexpected response while closing thread");
+                    Err(RecorderError::AudioError("Unexpected response".to_string()))
+                }
+                Err(e) => {
+                    error!("Failed to close audio thread: {}", e);
+                    Err(e)
+                }
+            };
 
-        match rx.recv() {
-            Ok(AudioResponse::Success(_)) => {
-                info!("Audio thread closed successfully");
-                Ok(())
-            }
-            Ok(AudioResponse::Error(e)) => {
-                error!("Error closing audio thread: {}", e);
-                Err(RecorderError::AudioError(e))
-            }
-            Ok(_) => {
-                error!("Unexpected response while closing thread");
-                Err(RecorderError::AudioError("Unexpected response".to_string()))
-            }
-            Err(e) => {
-                error!("Failed to receive thread close response: {}", e);
-                Err(RecorderError::ReceiveError(e.to_string()))
+            if thread.handle.join().is_err() {
+                error!("Audio thread panicked");
             }
+            result
+        } else {
+            debug!("No audio thread to close");
+            Ok(())
         }
-    } else {
-        debug!("No audio thread to close");
-        Ok(())
     }
-}
 
-pub fn start_recording(recording_id: String) -> Result<()> {
-    let filename = format!("{}.wav", recording_id);
+    /// Records into `<recording_id>.wav` in the current directory.
+    pub fn start_recording(&self, recording_id: String) -> Result<()> {
+        self.start_recording_as(recording_id, OutputFormat::Wav)
+    }
 
-    with_thread(|tx, rx| {
-        tx.send(AudioCommand::StartRecording(filename.clone()))
-            .map_err(|e| RecorderError::SendError(e.to_string()))?;
+    /// Records into `<recording_id>.<ext>` in the current directory, encoded
+    /// as `format` while recording. `stop_recording` returns the encoded bytes.
+    pub fn start_recording_as(&self, recording_id: String, format: OutputFormat) -> Result<()> {
+        self.start_recording_with(
+            RecordingTarget::File(format!("{}.{}", recording_id, format.extension())),
+            RecordingOptions {
+                format,
+                ..RecordingOptions::default()
+            },
+        )
+    }
 
-        match rx.recv() {
-            Ok(AudioResponse::Success(_)) => {
-                *CURRENT_RECORDING.lock().unwrap() = Some(filename);
-                Ok(())
-            }
-            Ok(AudioResponse::Error(e)) => Err(RecorderError::AudioError(e)),
-            Ok(_) => Err(RecorderError::AudioError("Unexpected response".to_string())),
-            Err(e) => Err(RecorderError::ReceiveError(e.to_string())),
+    /// Records into a buffer owned by the audio thread, so nothing touches
+    /// the filesystem. `stop_recording` returns the WAV bytes.
+    pub fn start_recording_in_memory(&self) -> Result<()> {
+        self.start(RecordingTarget::Memory)
+    }
+
+    /// Streams the WAV into a caller-supplied sink. The audio thread owns the
+    /// sink until the recording stops or is cancelled, and `stop_recording`
+    /// returns an empty buffer since the bytes never pass through the recorder.
+    pub fn start_recording_to_sink(&self, sink: Box<dyn RecordingSink>) -> Result<()> {
+        self.start(RecordingTarget::Sink(sink))
+    }
+
+    /// Records into any target with per-recording options such as a
+    /// maximum duration or segmentation. Segmented recordings stay on disk;
+    /// `stop_recording` returns an empty buffer and the segment paths.
+    pub fn start_recording_with(
+        &self,
+        target: RecordingTarget,
+        options: RecordingOptions,
+    ) -> Result<()> {
+        self.begin(target, options, AudioCommand::StartRecording)
+    }
+
+    /// Opens `target` now but only starts writing once the input level
+    /// crosses the session's speech threshold. A `RecordingTriggered` event
+    /// marks the moment; the session's pre-roll is kept.
+    pub fn arm_recording(&self, target: RecordingTarget, options: RecordingOptions) -> Result<()> {
+        self.begin(target, options, AudioCommand::ArmRecording)
+    }
+
+    fn start(&self, target: RecordingTarget) -> Result<()> {
+        self.start_recording_with(target, RecordingOptions::default())
+    }
+
+    fn begin(
+        &self,
+        target: RecordingTarget,
+        options: RecordingOptions,
+        command: fn(RecordingTarget, RecordingOptions) -> AudioCommand,
+    ) -> Result<()> {
+        let output = options.output(&target);
+        match self.request(command(target, options))? {
+            AudioResponse::Success(_) => self.set_current_recording(Some(output)),
+            AudioResponse::Error(e) => Err(RecorderError::AudioError(e)),
+            _ => Err(RecorderError::AudioError("Unexpected response".to_string())),
         }
-    })
-}
+    }
 
-pub fn stop_recording() -> Result<Vec<u8>> {
-    debug!("Stopping recording");
-    with_thread(|tx, rx| {
-        let current_recording = CURRENT_RECORDING
-            .lock()
-            .map_err(|e| RecorderError::LockError(e.to_string()))?
-            .clone();
-        let filename = current_recording.ok_or(RecorderError::NoActiveRecording)?;
+    pub fn stop_recording(&self) -> Result<Recording> {
+        debug!("Stopping recording");
+        self.current_recording()?;
 
-        tx.send(AudioCommand::StopRecording)
-            .map_err(|e| RecorderError::SendError(e.to_string()))?;
+        match self.request(AudioCommand::StopRecording)? {
+            AudioResponse::RecordingStopped(StoppedRecording {
+                data: Some(data),
+                output: _,
+                format,
+                input_stats,
+                segments,
+            }) => {
+                self.set_current_recording(None)?;
 
-        match rx.recv() {
-            Ok(AudioResponse::Success(_)) => {
-                debug!("Reading WAV file contents");
+                info!("Recording stopped successfully ({} bytes)", data.len());
+                Ok(Recording {
+                    data,
+                    mime_type: format.mime_type(),
+                    input_stats,
+                    segments,
+                })
+            }
+            AudioResponse::RecordingStopped(StoppedRecording {
+                data: None,
+                output,
+                format,
+                input_stats,
+                segments,
+            }) => {
+                let filename = match output {
+                    RecordingOutput::File(filename) => filename,
+                    RecordingOutput::Segments(_) | RecordingOutput::Sink => {
+                        self.set_current_recording(None)?;
+                        info!("Recording stopped successfully (written to segments or sink)");
+                        return Ok(Recording {
+                            data: Vec::new(),
+                            mime_type: format.mime_type(),
+                            input_stats,
+                            segments,
+                        });
+                    }
+                    RecordingOutput::Memory => {
+                        error!("In-memory recording finished without data");
+                        return Err(RecorderError::AudioError("Unexpected response".to_string()));
+                    }
+                };
+
+                debug!("Reading recorded file contents");
                 let contents = std::fs::read(&filename)?;
 
                 debug!("Cleaning up temporary file");
@@ -226,45 +417,76 @@ pub fn stop_recording() -> Result<Vec<u8>> {
                     warn!("Failed to clean up temporary file: {}", e);
                 }
 
-                *CURRENT_RECORDING
-                    .lock()
-                    .map_err(|e| RecorderError::LockError(e.to_string()))? = None;
+                self.set_current_recording(None)?;
 
                 info!("Recording stopped successfully ({} bytes)", contents.len());
-                Ok(contents)
+                Ok(Recording {
+                    data: contents,
+                    mime_type: format.mime_type(),
+                    input_stats,
+                    segments,
+                })
             }
-            Ok(AudioResponse::Error(e)) => {
+            AudioResponse::Error(e) => {
                 error!("Failed to stop recording: {}", e);
                 Err(RecorderError::AudioError(e))
             }
-            Ok(_) => {
+            _ => {
                 error!("Unexpected response while stopping recording");
                 Err(RecorderError::AudioError("Unexpected response".to_string()))
             }
-            Err(e) => {
-                error!("Failed to receive stop recording response: {}", e);
-                Err(RecorderError::ReceiveError(e.to_string()))
-            }
         }
-    })
-}
-
-pub fn cancel_recording() -> Result<()> {
-    with_thread(|tx, rx| {
-        let current_recording = CURRENT_RECORDING.lock().unwrap().clone();
-        let filename = current_recording.ok_or(RecorderError::NoActiveRecording)?;
+    }
 
-        tx.send(AudioCommand::CancelRecording(filename))
-            .map_err(|e| RecorderError::SendError(e.to_string()))?;
+    /// Stops writing samples without closing the stream or the WAV file.
+    pub fn pause_recording(&self) -> Result<()> {
+        debug!("Pausing recording");
+        match self.request(AudioCommand::PauseRecording)? {
+            AudioResponse::Success(_) => {
+                info!("Recording paused");
+                Ok(())
+            }
+            AudioResponse::Error(e) => Err(RecorderError::AudioError(e)),
+            _ => Err(RecorderError::AudioError("Unexpected response".to_string())),
+        }
+    }
 
-        match rx.recv() {
-            Ok(AudioResponse::Success(_)) => {
-                *CURRENT_RECORDING.lock().unwrap() = None;
+    pub fn resume_recording(&self) -> Result<()> {
+        debug!("Resuming recording");
+        match self.request(AudioCommand::ResumeRecording)? {
+            AudioResponse::Success(_) => {
+                info!("Recording resumed");
                 Ok(())
             }
-            Ok(AudioResponse::Error(e)) => Err(RecorderError::AudioError(e)),
-            Ok(_) => Err(RecorderError::AudioError("Unexpected response".to_string())),
-            Err(e) => Err(RecorderError::ReceiveError(e.to_string())),
+            AudioResponse::Error(e) => Err(RecorderError::AudioError(e)),
+            _ => Err(RecorderError::AudioError("Unexpected response".to_string())),
+        }
+    }
+
+    /// Asks the audio thread for a snapshot of its session and recording.
+    pub fn get_state(&self) -> Result<RecorderStatus> {
+        match self.request(AudioCommand::GetState)? {
+            AudioResponse::State(status) => Ok(*status),
+            AudioResponse::Error(e) => Err(RecorderError::AudioError(e)),
+            _ => Err(RecorderError::AudioError("Unexpected response".to_string())),
         }
-    })
+    }
+
+    pub fn cancel_recording(&self) -> Result<()> {
+        self.current_recording()?;
+
+        match self.request(AudioCommand::CancelRecording)? {
+            AudioResponse::Success(_) => self.set_current_recording(None),
+            AudioResponse::Error(e) => Err(RecorderError::AudioError(e)),
+            _ => Err(RecorderError::AudioError("Unexpected response".to_string())),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.close_thread() {
+            warn!("Failed to close audio thread on drop: {}", e);
+        }
+    }
 }
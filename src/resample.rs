@@ -0,0 +1,301 @@
+use std::f64::consts::PI;
+
+/// Zero crossings of the sinc kernel on each side of the output sample, at
+/// the lower of the two rates.
+const SINC_ZERO_CROSSINGS: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResampleQuality {
+    /// Linear interpolation. Cheap, but lets some aliasing through when
+    /// downsampling.
+    #[default]
+    Fast,
+    /// Blackman-windowed sinc with an anti-aliasing low-pass.
+    High,
+}
+
+/// Streaming sample-rate converter for interleaved f32 audio. Input may arrive
+/// in blocks of any size; the converter keeps just enough history between
+/// calls for the interpolation kernel.
+pub struct Resampler {
+    channels: usize,
+    from_rate: u32,
+    to_rate: u32,
+    /// Input frames consumed per output frame.
+    step: f64,
+    /// Low-pass cutoff relative to the input Nyquist frequency.
+    cutoff: f64,
+    /// Kernel half-width in input frames.
+    half_width: usize,
+    quality: ResampleQuality,
+    /// Buffered input frames, interleaved.
+    buffer: Vec<f32>,
+    /// Position of the next output frame, in input frames from the buffer start.
+    position: f64,
+    /// Frames passed to `process`, so `flush` knows where the input ends.
+    input_frames: u64,
+    output_frames: u64,
+}
+
+impl Resampler {
+    pub fn new(channels: u16, from_rate: u32, to_rate: u32, quality: ResampleQuality) -> Self {
+        let step = from_rate as f64 / to_rate as f64;
+        let cutoff = (to_rate as f64 / from_rate as f64).min(1.0) * 0.95;
+        let half_width = match quality {
+            ResampleQuality::Fast => 1,
+            ResampleQuality::High => (SINC_ZERO_CROSSINGS as f64 / cutoff).ceil() as usize,
+        };
+        let channels = channels.max(1) as usize;
+
+        // Prime the history with silence so the first output frame has a full
+        // kernel to its left.
+        let lead_in = match quality {
+            ResampleQuality::Fast => 0,
+            ResampleQuality::High => half_width,
+        };
+
+        Self {
+            channels,
+            from_rate,
+            to_rate,
+            step,
+            cutoff,
+            half_width,
+            quality,
+            buffer: vec![0.0; lead_in * channels],
+            position: lead_in as f64,
+            input_frames: 0,
+            output_frames: 0,
+        }
+    }
+
+    fn buffered_frames(&self) -> usize {
+        self.buffer.len() / self.channels
+    }
+
+    /// Resamples `input` and appends the result to `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        self.input_frames += (input.len() / self.channels) as u64;
+        self.resample(input, output);
+    }
+
+    fn resample(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        self.buffer.extend_from_slice(input);
+
+        let frames = self.buffered_frames();
+        while (self.position.floor() as usize) + self.half_width < frames {
+            match self.quality {
+                ResampleQuality::Fast => self.interpolate_linear(output),
+                ResampleQuality::High => self.interpolate_sinc(output),
+            }
+            self.position += self.step;
+            self.output_frames += 1;
+        }
+
+        // Drop frames that no future output frame can reach.
+        let keep_from = match self.quality {
+            ResampleQuality::Fast => self.position.floor() as usize,
+            ResampleQuality::High => {
+                (self.position.floor() as usize + 1).saturating_sub(self.half_width)
+            }
+        }
+        .min(frames);
+        self.buffer.drain(..keep_from * self.channels);
+        self.position -= keep_from as f64;
+    }
+
+    /// Pads the input with silence so the tail of the signal is emitted,
+    /// stopping at the last output frame that falls within the input.
+    pub fn flush(&mut self, output: &mut Vec<f32>) {
+        // Output frame k sits at input time k * step, so the input covers
+        // ceil(input_frames / step) of them.
+        let total = (self.input_frames * self.to_rate as u64).div_ceil(self.from_rate as u64);
+        let remaining = total.saturating_sub(self.output_frames) as usize;
+        let start = output.len();
+        let padding = vec![0.0; (self.half_width + 1) * self.channels];
+        self.resample(&padding, output);
+        output.truncate(start + remaining * self.channels);
+    }
+
+    fn interpolate_linear(&self, output: &mut Vec<f32>) {
+        let index = self.position.floor() as usize;
+        let fraction = (self.position - index as f64) as f32;
+        let current = &self.buffer[index * self.channels..(index + 1) * self.channels];
+        let next = &self.buffer[(index + 1) * self.channels..(index + 2) * self.channels];
+        output.extend(
+            current
+                .iter()
+                .zip(next)
+                .map(|(&a, &b)| a + (b - a) * fraction),
+        );
+    }
+
+    fn interpolate_sinc(&self, output: &mut Vec<f32>) {
+        let index = self.position.floor() as usize;
+        let first = (index + 1).saturating_sub(self.half_width);
+        let last = index + self.half_width;
+
+        let start = output.len();
+        output.extend(std::iter::repeat_n(0.0, self.channels));
+        let mut weight_sum = 0.0;
+
+        for frame in first..=last {
+            let distance = self.position - frame as f64;
+            let weight = self.kernel(distance);
+            if weight == 0.0 {
+                continue;
+            }
+            weight_sum += weight;
+            let samples = &self.buffer[frame * self.channels..(frame + 1) * self.channels];
+            for (out, &sample) in output[start..].iter_mut().zip(samples) {
+                *out += (sample as f64 * weight) as f32;
+            }
+        }
+
+        // Normalize so DC passes at unity gain regardless of the fractional phase.
+        if weight_sum > 0.0 {
+            for out in &mut output[start..] {
+                *out = (*out as f64 / weight_sum) as f32;
+            }
+        }
+    }
+
+    fn kernel(&self, distance: f64) -> f64 {
+        let half_width = self.half_width as f64;
+        if distance.abs() >= half_width {
+            return 0.0;
+        }
+        let x = distance * self.cutoff;
+        let sinc = if x == 0.0 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        };
+        let t = (distance + half_width) / (2.0 * half_width);
+        let window = 0.42 - 0.5 * (2.0 * PI * t).cos() + 0.08 * (4.0 * PI * t).cos();
+        sinc * window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUALITIES: [ResampleQuality; 2] = [ResampleQuality::Fast, ResampleQuality::High];
+
+    fn tone(frequency: f32, rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / rate as f32).sin())
+            .collect()
+    }
+
+    /// Resamples `input` in blocks of `block_frames` and flushes.
+    fn resample(
+        input: &[f32],
+        channels: u16,
+        from_rate: u32,
+        to_rate: u32,
+        quality: ResampleQuality,
+        block_frames: usize,
+    ) -> Vec<f32> {
+        let mut resampler = Resampler::new(channels, from_rate, to_rate, quality);
+        let mut output = Vec::new();
+        for block in input.chunks(block_frames * channels as usize) {
+            resampler.process(block, &mut output);
+        }
+        resampler.flush(&mut output);
+        output
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn output_length_matches_the_input_duration() {
+        let rates = [
+            (48_000, 16_000),
+            (16_000, 48_000),
+            (44_100, 48_000),
+            (48_000, 44_100),
+        ];
+        for quality in QUALITIES {
+            for (from_rate, to_rate) in rates {
+                for frames in [0, 1, 479, 480, 48_000, 48_017] {
+                    let input = vec![0.25; frames * 2];
+                    let output = resample(&input, 2, from_rate, to_rate, quality, 480);
+                    let expected = (frames as u64 * to_rate as u64).div_ceil(from_rate as u64);
+                    assert_eq!(
+                        output.len(),
+                        expected as usize * 2,
+                        "{} frames {} -> {} at {:?}",
+                        frames,
+                        from_rate,
+                        to_rate,
+                        quality
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn block_size_does_not_change_the_output() {
+        let input = tone(440.0, 44_100, 10_000);
+        for quality in QUALITIES {
+            let whole = resample(&input, 1, 44_100, 16_000, quality, input.len());
+            for block_frames in [1, 7, 480, 4096] {
+                let blocked = resample(&input, 1, 44_100, 16_000, quality, block_frames);
+                assert_eq!(blocked.len(), whole.len());
+                // Only rounding in the fractional position may differ.
+                for (a, b) in blocked.iter().zip(&whole) {
+                    assert!((a - b).abs() < 1e-4, "{} vs {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dc_passes_at_unity_gain_per_channel() {
+        let input: Vec<f32> = [0.5, -0.25].repeat(4800);
+        for quality in QUALITIES {
+            let output = resample(&input, 2, 48_000, 16_000, quality, 480);
+            // Skip the edges, where the kernel runs into the silent padding.
+            let frames = output.len() / 2;
+            for frame in output.chunks_exact(2).take(frames - 100).skip(100) {
+                assert!((frame[0] - 0.5).abs() < 1e-3, "{:?}", frame);
+                assert!((frame[1] + 0.25).abs() < 1e-3, "{:?}", frame);
+            }
+        }
+    }
+
+    #[test]
+    fn high_quality_removes_content_above_the_new_nyquist() {
+        let passband = resample(
+            &tone(1_000.0, 48_000, 48_000),
+            1,
+            48_000,
+            16_000,
+            ResampleQuality::High,
+            480,
+        );
+        let aliased = resample(
+            &tone(12_000.0, 48_000, 48_000),
+            1,
+            48_000,
+            16_000,
+            ResampleQuality::High,
+            480,
+        );
+        let middle = 1000..15_000;
+        assert!(rms(&passband[middle.clone()]) > 0.65);
+        assert!(rms(&aliased[middle]) < 0.01);
+    }
+
+    #[test]
+    fn equal_rates_pass_samples_through() {
+        let input = tone(440.0, 48_000, 1000);
+        let output = resample(&input, 1, 48_000, 48_000, ResampleQuality::Fast, 480);
+        assert_eq!(output, input);
+    }
+}
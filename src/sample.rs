@@ -0,0 +1,188 @@
+use crate::backend::InputBuffer;
+use std::io::{Seek, Write};
+
+/// Checks that hound can write the requested encoding.
+pub fn validate_output_format(
+    bits_per_sample: u16,
+    sample_format: hound::SampleFormat,
+) -> Result<(), String> {
+    match (sample_format, bits_per_sample) {
+        (hound::SampleFormat::Int, 16 | 24 | 32) | (hound::SampleFormat::Float, 32) => Ok(()),
+        (hound::SampleFormat::Int, bits) => Err(format!(
+            "Unsupported bits_per_sample for integer output: {} (expected 16, 24 or 32)",
+            bits
+        )),
+        (hound::SampleFormat::Float, bits) => Err(format!(
+            "Unsupported bits_per_sample for float output: {} (expected 32)",
+            bits
+        )),
+    }
+}
+
+/// Appends `input` to `out` as f32 samples scaled to [-1.0, 1.0).
+pub fn append_normalized(input: InputBuffer<'_>, out: &mut Vec<f32>) {
+    match input {
+        InputBuffer::I8(data) => out.extend(data.iter().map(|&s| s as f32 / 128.0)),
+        InputBuffer::I16(data) => out.extend(data.iter().map(|&s| s as f32 / 32_768.0)),
+        InputBuffer::I32(data) => {
+            out.extend(data.iter().map(|&s| (s as f64 / 2_147_483_648.0) as f32))
+        }
+        InputBuffer::F32(data) => out.extend_from_slice(data),
+    }
+}
+
+/// Scales a normalized sample to a signed integer of `bits` width, rounding
+/// and clamping to the representable range. 24-bit values are returned in the
+/// low bits of the i32, which is what hound packs into three bytes.
+pub(crate) fn quantize(sample: f32, bits: u16) -> i32 {
+    let max = ((1i64 << (bits - 1)) - 1) as f64;
+    let min = -(1i64 << (bits - 1)) as f64;
+    (sample as f64 * (1i64 << (bits - 1)) as f64)
+        .round()
+        .clamp(min, max) as i32
+}
+
+/// Writes normalized samples using the writer's bit depth and sample format.
+pub fn write_normalized<W: Write + Seek>(
+    writer: &mut hound::WavWriter<W>,
+    samples: &[f32],
+) -> hound::Result<()> {
+    let spec = writer.spec();
+    match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Float, _) => {
+            for &sample in samples {
+                writer.write_sample(sample)?;
+            }
+        }
+        (hound::SampleFormat::Int, 16) => {
+            for &sample in samples {
+                writer.write_sample(quantize(sample, 16) as i16)?;
+            }
+        }
+        (hound::SampleFormat::Int, bits) => {
+            for &sample in samples {
+                writer.write_sample(quantize(sample, bits))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn normalized(input: InputBuffer<'_>) -> Vec<f32> {
+        let mut out = Vec::new();
+        append_normalized(input, &mut out);
+        out
+    }
+
+    /// Writes `samples` as a mono WAV and returns the file's bytes.
+    fn wav(sample_format: hound::SampleFormat, bits_per_sample: u16, samples: &[f32]) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 16_000,
+            bits_per_sample,
+            sample_format,
+        };
+        let mut bytes = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+        write_normalized(&mut writer, samples).unwrap();
+        writer.finalize().unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn normalizes_every_input_format_at_full_scale() {
+        assert_eq!(
+            normalized(InputBuffer::I8(&[i8::MIN, 0, 64])),
+            [-1.0, 0.0, 0.5]
+        );
+        assert_eq!(
+            normalized(InputBuffer::I16(&[i16::MIN, 0, 16_384])),
+            [-1.0, 0.0, 0.5]
+        );
+        assert_eq!(
+            normalized(InputBuffer::I32(&[i32::MIN, 0, 1 << 30])),
+            [-1.0, 0.0, 0.5]
+        );
+        assert_eq!(
+            normalized(InputBuffer::F32(&[-1.0, 0.0, 0.5])),
+            [-1.0, 0.0, 0.5]
+        );
+
+        let max = normalized(InputBuffer::I16(&[i16::MAX]))[0];
+        assert!(max < 1.0 && max > 0.9999);
+    }
+
+    #[test]
+    fn quantizes_with_rounding_and_clamping() {
+        assert_eq!(quantize(-1.0, 16), i16::MIN as i32);
+        assert_eq!(quantize(1.0, 16), i16::MAX as i32);
+        assert_eq!(quantize(1.5, 16), i16::MAX as i32);
+        assert_eq!(quantize(-1.5, 16), i16::MIN as i32);
+        assert_eq!(quantize(0.5, 16), 16_384);
+        // 0.4 and 0.6 LSB either side of zero.
+        assert_eq!(quantize(0.4 / 32_768.0, 16), 0);
+        assert_eq!(quantize(0.6 / 32_768.0, 16), 1);
+        assert_eq!(quantize(-0.6 / 32_768.0, 16), -1);
+
+        assert_eq!(quantize(-1.0, 24), -(1 << 23));
+        assert_eq!(quantize(1.0, 24), (1 << 23) - 1);
+        assert_eq!(quantize(-1.0, 32), i32::MIN);
+        assert_eq!(quantize(1.0, 32), i32::MAX);
+    }
+
+    #[test]
+    fn integer_samples_round_trip_through_normalization() {
+        let input = [i16::MIN, -12_345, -1, 0, 1, 12_345, i16::MAX];
+        let samples = normalized(InputBuffer::I16(&input));
+        let written: Vec<i16> =
+            hound::WavReader::new(Cursor::new(wav(hound::SampleFormat::Int, 16, &samples)))
+                .unwrap()
+                .into_samples()
+                .map(Result::unwrap)
+                .collect();
+        assert_eq!(written, input);
+    }
+
+    #[test]
+    fn packs_24_bit_samples_into_three_bytes() {
+        let bytes = wav(hound::SampleFormat::Int, 24, &[-1.0, 1.0, 0.5]);
+        // The data chunk ends the file, three little-endian bytes per sample.
+        assert_eq!(
+            bytes[bytes.len() - 9..],
+            [0x00, 0x00, 0x80, 0xff, 0xff, 0x7f, 0x00, 0x00, 0x40]
+        );
+        let written: Vec<i32> = hound::WavReader::new(Cursor::new(bytes))
+            .unwrap()
+            .into_samples()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(written, [-(1 << 23), (1 << 23) - 1, 1 << 22]);
+    }
+
+    #[test]
+    fn float_output_keeps_samples_unchanged() {
+        let samples = [-1.0, -0.25, 0.0, 0.75, 1.0];
+        let written: Vec<f32> =
+            hound::WavReader::new(Cursor::new(wav(hound::SampleFormat::Float, 32, &samples)))
+                .unwrap()
+                .into_samples()
+                .map(Result::unwrap)
+                .collect();
+        assert_eq!(written, samples);
+    }
+
+    #[test]
+    fn validates_output_formats() {
+        for bits in [16, 24, 32] {
+            assert!(validate_output_format(bits, hound::SampleFormat::Int).is_ok());
+        }
+        assert!(validate_output_format(32, hound::SampleFormat::Float).is_ok());
+        assert!(validate_output_format(8, hound::SampleFormat::Int).is_err());
+        assert!(validate_output_format(16, hound::SampleFormat::Float).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::events::{EventBus, RecorderEvent};
+use std::time::Duration;
+
+/// When a segmented recording moves on to the next file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentLimit {
+    Duration(Duration),
+    /// File size in bytes, header included. WAV output only, since the
+    /// size of compressed segments can't be known up front.
+    Bytes(u64),
+}
+
+/// A segment file that has been finalized and can be consumed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletedSegment {
+    /// One-based, matching the number in the file name.
+    pub index: u32,
+    pub path: String,
+    pub frames: u64,
+}
+
+/// Largest header hound writes (WAVE_FORMAT_EXTENSIBLE).
+const MAX_WAV_HEADER_BYTES: u64 = 68;
+
+/// Path of segment `index` for a recording that would otherwise be written
+/// to `path`, e.g. `meeting.wav` becomes `meeting_0001.wav`. The extension
+/// is kept, so FLAC recordings get `.flac` segments.
+pub fn segment_path(path: &str, index: u32) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, extension)) if !extension.contains(['/', '\\']) => {
+            format!("{}_{:04}.{}", stem, index, extension)
+        }
+        _ => format!("{}_{:04}", path, index),
+    }
+}
+
+/// Tracks which segment the writer is on and announces finished ones.
+pub(crate) struct Segmenter {
+    path: String,
+    limit: SegmentLimit,
+    index: u32,
+    frames_per_segment: u64,
+    frames_in_segment: u64,
+    /// Segments finalized so far, in order.
+    completed: Vec<CompletedSegment>,
+    events: EventBus,
+}
+
+impl Segmenter {
+    pub(crate) fn new(
+        path: String,
+        limit: SegmentLimit,
+        spec: hound::WavSpec,
+        events: EventBus,
+    ) -> Self {
+        let frames_per_segment = match limit {
+            SegmentLimit::Duration(duration) => {
+                (duration.as_secs_f64() * spec.sample_rate as f64).round() as u64
+            }
+            SegmentLimit::Bytes(bytes) => {
+                let frame_bytes = spec.channels as u64 * spec.bits_per_sample as u64 / 8;
+                bytes.saturating_sub(MAX_WAV_HEADER_BYTES) / frame_bytes.max(1)
+            }
+        };
+        Self {
+            path,
+            limit,
+            index: 1,
+            frames_per_segment: frames_per_segment.max(1),
+            frames_in_segment: 0,
+            completed: Vec::new(),
+            events,
+        }
+    }
+
+    /// The path the recording was started with, before segment numbering.
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub(crate) fn limit(&self) -> SegmentLimit {
+        self.limit
+    }
+
+    pub(crate) fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Picks up a recording that was interrupted after `completed`, so it
+    /// carries on at segment `index` and reports every segment at the end.
+    pub(crate) fn continue_from(&mut self, index: u32, completed: Vec<CompletedSegment>) {
+        self.index = index;
+        self.completed = completed;
+    }
+
+    pub(crate) fn current_path(&self) -> String {
+        segment_path(&self.path, self.index)
+    }
+
+    /// Frames the current segment can still take before it must rotate.
+    pub(crate) fn remaining(&self) -> u64 {
+        self.frames_per_segment - self.frames_in_segment
+    }
+
+    pub(crate) fn record(&mut self, frames: u64) {
+        self.frames_in_segment += frames;
+    }
+
+    pub(crate) fn next_path(&self) -> String {
+        segment_path(&self.path, self.index + 1)
+    }
+
+    /// Announces the current segment as finalized and moves to the next one.
+    pub(crate) fn advance(&mut self) {
+        self.complete();
+        self.index += 1;
+        self.frames_in_segment = 0;
+    }
+
+    /// Announces the current segment as finalized.
+    pub(crate) fn complete(&mut self) {
+        let segment = CompletedSegment {
+            index: self.index,
+            path: self.current_path(),
+            frames: self.frames_in_segment,
+        };
+        self.completed.push(segment.clone());
+        self.events.emit(RecorderEvent::SegmentCompleted(segment));
+    }
+
+    /// The segments finalized so far.
+    pub(crate) fn into_completed(self) -> Vec<CompletedSegment> {
+        self.completed
+    }
+}
@@ -0,0 +1,93 @@
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+/// Anything a WAV can be streamed into. hound seeks back to patch the header
+/// on finalize, so the sink must be seekable.
+pub trait RecordingSink: Write + Seek + Send {}
+
+impl<T: Write + Seek + Send> RecordingSink for T {}
+
+/// Where a recording is written.
+pub enum RecordingTarget {
+    /// A WAV file at this path.
+    File(String),
+    /// A buffer owned by the audio thread and handed back on stop.
+    Memory,
+    /// A caller-supplied sink, e.g. an encrypted container. The recorder
+    /// never reads it back.
+    Sink(Box<dyn RecordingSink>),
+}
+
+impl RecordingTarget {
+    pub fn output(&self) -> RecordingOutput {
+        match self {
+            RecordingTarget::File(filename) => RecordingOutput::File(filename.clone()),
+            RecordingTarget::Memory => RecordingOutput::Memory,
+            RecordingTarget::Sink(_) => RecordingOutput::Sink,
+        }
+    }
+
+    /// Opens the target for writing. In-memory targets also return a handle
+    /// for reading the bytes back after the writer is finalized.
+    pub(crate) fn open(self) -> std::io::Result<(Box<dyn RecordingSink>, Option<MemoryBuffer>)> {
+        match self {
+            RecordingTarget::File(filename) => {
+                Ok((Box::new(BufWriter::new(File::create(filename)?)), None))
+            }
+            RecordingTarget::Memory => {
+                let buffer = MemoryBuffer::default();
+                Ok((Box::new(buffer.clone()), Some(buffer)))
+            }
+            RecordingTarget::Sink(sink) => Ok((sink, None)),
+        }
+    }
+}
+
+impl std::fmt::Debug for RecordingTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordingTarget::File(filename) => f.debug_tuple("File").field(filename).finish(),
+            RecordingTarget::Memory => f.write_str("Memory"),
+            RecordingTarget::Sink(_) => f.write_str("Sink(..)"),
+        }
+    }
+}
+
+/// Describes where the active recording is going, without owning the sink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordingOutput {
+    File(String),
+    /// Numbered segment files derived from this path, see
+    /// `segment::segment_path`.
+    Segments(String),
+    Memory,
+    Sink,
+}
+
+/// In-memory WAV buffer. hound's `finalize` consumes the writer, so the
+/// audio thread keeps a second handle to read the bytes back afterwards.
+#[derive(Clone, Default)]
+pub(crate) struct MemoryBuffer(Arc<Mutex<Cursor<Vec<u8>>>>);
+
+impl MemoryBuffer {
+    pub(crate) fn take(&self) -> Vec<u8> {
+        std::mem::take(self.0.lock().unwrap().get_mut())
+    }
+}
+
+impl Write for MemoryBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryBuffer {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.0.lock().unwrap().seek(pos)
+    }
+}
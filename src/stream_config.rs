@@ -0,0 +1,254 @@
+use crate::backend::DeviceId;
+
+/// Input stream settings to ask the device for. Unset fields keep the value
+/// of the device's default input config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamRequest {
+    /// Rate the device captures at. Unlike `target_sample_rate`, this
+    /// changes what the hardware delivers rather than resampling afterwards.
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub sample_format: Option<cpal::SampleFormat>,
+    /// Frames per callback. `Fixed` sizes are clamped to what the device
+    /// supports.
+    pub buffer_size: cpal::BufferSize,
+}
+
+impl Default for StreamRequest {
+    fn default() -> Self {
+        Self {
+            sample_rate: None,
+            channels: None,
+            sample_format: None,
+            buffer_size: cpal::BufferSize::Default,
+        }
+    }
+}
+
+impl StreamRequest {
+    /// True when only the buffer size (if anything) is requested, so the
+    /// device's default config can be used without looking at the others.
+    pub fn keeps_default_config(&self) -> bool {
+        self.sample_rate.is_none() && self.channels.is_none() && self.sample_format.is_none()
+    }
+}
+
+/// The input stream a session actually opened, as reported when it is
+/// initialized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NegotiatedStream {
+    pub device_id: DeviceId,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub sample_format: cpal::SampleFormat,
+    pub buffer_size: cpal::BufferSize,
+}
+
+/// Sample formats the input callback can convert from.
+fn is_convertible(format: cpal::SampleFormat) -> bool {
+    matches!(
+        format,
+        cpal::SampleFormat::I8
+            | cpal::SampleFormat::I16
+            | cpal::SampleFormat::I32
+            | cpal::SampleFormat::F32
+    )
+}
+
+/// Picks the supported config closest to `request`, preferring in turn the
+/// requested channel count, the requested sample rate and the requested
+/// sample format. The default config is used as is when nothing is
+/// requested.
+pub fn negotiate(
+    request: &StreamRequest,
+    default: &cpal::SupportedStreamConfig,
+    supported: &[cpal::SupportedStreamConfigRange],
+) -> Result<(cpal::SupportedStreamConfig, cpal::BufferSize), String> {
+    let config = if request.keeps_default_config() {
+        default.clone()
+    } else {
+        let channels = request.channels.unwrap_or(default.channels());
+        let sample_rate = request.sample_rate.unwrap_or(default.sample_rate().0);
+        let sample_format = request.sample_format.unwrap_or(default.sample_format());
+        supported
+            .iter()
+            .filter(|range| is_convertible(range.sample_format()))
+            .map(|range| {
+                let rate = sample_rate.clamp(range.min_sample_rate().0, range.max_sample_rate().0);
+                range.with_sample_rate(cpal::SampleRate(rate))
+            })
+            .min_by_key(|config| {
+                (
+                    config.channels().abs_diff(channels),
+                    config.sample_rate().0.abs_diff(sample_rate),
+                    config.sample_format() != sample_format,
+                )
+            })
+            .ok_or_else(|| "Device reports no supported input configs".to_string())?
+    };
+
+    let buffer_size = match (request.buffer_size, config.buffer_size()) {
+        (cpal::BufferSize::Fixed(frames), cpal::SupportedBufferSize::Range { min, max }) => {
+            cpal::BufferSize::Fixed(frames.clamp(*min, *max))
+        }
+        (buffer_size, _) => buffer_size,
+    };
+    Ok((config, buffer_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::{SampleFormat, SampleRate, SupportedBufferSize, SupportedStreamConfigRange};
+
+    const BUFFER: SupportedBufferSize = SupportedBufferSize::Range { min: 64, max: 1024 };
+
+    fn range(
+        channels: u16,
+        min: u32,
+        max: u32,
+        format: SampleFormat,
+    ) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(channels, SampleRate(min), SampleRate(max), BUFFER, format)
+    }
+
+    fn default_config() -> cpal::SupportedStreamConfig {
+        range(2, 48_000, 48_000, SampleFormat::F32).with_max_sample_rate()
+    }
+
+    fn negotiated(
+        request: StreamRequest,
+        supported: &[SupportedStreamConfigRange],
+    ) -> (u16, u32, SampleFormat) {
+        let (config, _) = negotiate(&request, &default_config(), supported).unwrap();
+        (
+            config.channels(),
+            config.sample_rate().0,
+            config.sample_format(),
+        )
+    }
+
+    #[test]
+    fn keeps_the_default_config_when_nothing_is_requested() {
+        let (config, buffer_size) =
+            negotiate(&StreamRequest::default(), &default_config(), &[]).unwrap();
+        assert_eq!(config, default_config());
+        assert_eq!(buffer_size, cpal::BufferSize::Default);
+    }
+
+    #[test]
+    fn prefers_channels_then_rate_then_format() {
+        let supported = [
+            range(1, 8_000, 48_000, SampleFormat::I16),
+            range(2, 44_100, 44_100, SampleFormat::F32),
+            range(2, 44_100, 44_100, SampleFormat::I16),
+        ];
+        let request = |channels, sample_rate, sample_format| StreamRequest {
+            channels: Some(channels),
+            sample_rate: Some(sample_rate),
+            sample_format: Some(sample_format),
+            ..StreamRequest::default()
+        };
+        assert_eq!(
+            negotiated(request(2, 16_000, SampleFormat::I16), &supported),
+            (2, 44_100, SampleFormat::I16)
+        );
+        assert_eq!(
+            negotiated(request(2, 16_000, SampleFormat::I32), &supported),
+            (2, 44_100, SampleFormat::F32)
+        );
+        assert_eq!(
+            negotiated(request(1, 16_000, SampleFormat::F32), &supported),
+            (1, 16_000, SampleFormat::I16)
+        );
+    }
+
+    #[test]
+    fn fills_unset_fields_from_the_default_config() {
+        let supported = [
+            range(1, 8_000, 48_000, SampleFormat::I16),
+            range(2, 8_000, 48_000, SampleFormat::I16),
+            range(2, 8_000, 48_000, SampleFormat::F32),
+        ];
+        let request = StreamRequest {
+            sample_rate: Some(16_000),
+            ..StreamRequest::default()
+        };
+        assert_eq!(
+            negotiated(request, &supported),
+            (2, 16_000, SampleFormat::F32)
+        );
+    }
+
+    #[test]
+    fn clamps_the_rate_to_the_supported_range() {
+        let supported = [range(2, 8_000, 48_000, SampleFormat::F32)];
+        let request = |sample_rate| StreamRequest {
+            sample_rate: Some(sample_rate),
+            ..StreamRequest::default()
+        };
+        assert_eq!(negotiated(request(96_000), &supported).1, 48_000);
+        assert_eq!(negotiated(request(4_000), &supported).1, 8_000);
+        assert_eq!(negotiated(request(22_050), &supported).1, 22_050);
+    }
+
+    #[test]
+    fn skips_formats_it_cannot_convert() {
+        let request = StreamRequest {
+            sample_format: Some(SampleFormat::U8),
+            ..StreamRequest::default()
+        };
+        let supported = [
+            range(2, 48_000, 48_000, SampleFormat::U8),
+            range(2, 48_000, 48_000, SampleFormat::F64),
+            range(1, 16_000, 16_000, SampleFormat::I32),
+        ];
+        assert_eq!(
+            negotiated(request.clone(), &supported),
+            (1, 16_000, SampleFormat::I32)
+        );
+        assert!(negotiate(&request, &default_config(), &supported[..2]).is_err());
+        assert!(negotiate(&request, &default_config(), &[]).is_err());
+    }
+
+    #[test]
+    fn clamps_fixed_buffer_sizes() {
+        let buffer_size = |requested, supported: &[SupportedStreamConfigRange]| {
+            let request = StreamRequest {
+                channels: Some(2),
+                buffer_size: requested,
+                ..StreamRequest::default()
+            };
+            negotiate(&request, &default_config(), supported).unwrap().1
+        };
+        let supported = [range(2, 48_000, 48_000, SampleFormat::F32)];
+        assert_eq!(
+            buffer_size(cpal::BufferSize::Fixed(16), &supported),
+            cpal::BufferSize::Fixed(64)
+        );
+        assert_eq!(
+            buffer_size(cpal::BufferSize::Fixed(4096), &supported),
+            cpal::BufferSize::Fixed(1024)
+        );
+        assert_eq!(
+            buffer_size(cpal::BufferSize::Fixed(256), &supported),
+            cpal::BufferSize::Fixed(256)
+        );
+        assert_eq!(
+            buffer_size(cpal::BufferSize::Default, &supported),
+            cpal::BufferSize::Default
+        );
+
+        let unknown = [SupportedStreamConfigRange::new(
+            2,
+            SampleRate(48_000),
+            SampleRate(48_000),
+            SupportedBufferSize::Unknown,
+            SampleFormat::F32,
+        )];
+        assert_eq!(
+            buffer_size(cpal::BufferSize::Fixed(16), &unknown),
+            cpal::BufferSize::Fixed(16)
+        );
+    }
+}
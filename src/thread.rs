@@ -1,25 +1,123 @@
-use cpal::Sample;
-use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    Stream,
-};
-use std::sync::mpsc::{self, SendError};
+use crate::backend::{AudioBackend, DeviceId, InputBuffer, InputDeviceInfo, InputStream};
+use crate::capture::Capture;
+use crate::channels::ChannelPolicy;
+use crate::chunks::ChunkSubscribers;
+use crate::encoder::OutputFormat;
+use crate::events::{EventBus, RecorderEvent};
+use crate::health::{InputHealthConfig, InputStats};
+use crate::meter::MeterReading;
+use crate::reconnect::{Backoff, ReconnectConfig};
+use crate::resample::ResampleQuality;
+use crate::sample;
+use crate::segment::{CompletedSegment, SegmentLimit};
+use crate::sink::{RecordingOutput, RecordingTarget};
+use crate::stream_config::{self, NegotiatedStream, StreamRequest};
+use crate::trim::{self, TrimConfig};
+use crate::vad::VadConfig;
+use std::sync::mpsc::{self, RecvTimeoutError, SendError};
 use std::{
-    fs::File,
-    io::BufWriter,
     sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
 };
+use tracing::{debug, warn};
 
 #[derive(Debug)]
 pub struct UserRecordingSessionConfig {
+    /// A device ID from enumeration (`<host>:<name>#<n>`) or a display name.
+    /// A name shared by several devices is rejected; use the ID instead.
     pub device_name: String,
     pub bits_per_sample: u16,
+    /// Integer or IEEE float samples in the output WAV. Float requires 32 bits.
+    pub sample_format: hound::SampleFormat,
+    /// Sample rate of the output WAV. `None` keeps the device's rate.
+    pub target_sample_rate: Option<u32>,
+    pub resample_quality: ResampleQuality,
+    /// Applied in the input callback, before resampling and writing.
+    pub channel_policy: ChannelPolicy,
+    /// Audio kept from before `StartRecording` and written at the start of
+    /// each recording. Zero disables the buffer.
+    pub pre_roll: Duration,
+    /// Speech detection used by auto-stop.
+    pub vad: VadConfig,
+    /// Stop the recording on its own after this much trailing silence,
+    /// counted from the end of speech. Pauses shorter than `vad.hangover`
+    /// never count, and nothing counts before the first speech, so a
+    /// recording started in a quiet room keeps going. `None` disables
+    /// auto-stop.
+    pub auto_stop_after: Option<Duration>,
+    /// Trim leading and trailing silence when the recording stops. Not
+    /// available for caller-supplied sinks, which cannot be read back.
+    pub trim_silence: Option<TrimConfig>,
+    /// How often input levels are published to meter subscribers. Zero
+    /// disables metering.
+    pub meter_rate_hz: u32,
+    /// Thresholds for clipping and dead-input warnings.
+    pub input_health: InputHealthConfig,
+    /// Rate, channels, sample format and buffer size to open the device
+    /// with. The closest supported config is used when the device can't
+    /// match exactly.
+    pub stream: StreamRequest,
+    /// Reopen the stream when the device disappears. `None` leaves the
+    /// session in the error state, with the interrupted recording
+    /// finalized and ready to collect.
+    pub reconnect: Option<ReconnectConfig>,
+}
+
+impl Default for UserRecordingSessionConfig {
+    fn default() -> Self {
+        Self {
+            device_name: "default".to_string(),
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Int,
+            target_sample_rate: None,
+            resample_quality: ResampleQuality::default(),
+            channel_policy: ChannelPolicy::default(),
+            pre_roll: Duration::ZERO,
+            vad: VadConfig::default(),
+            auto_stop_after: None,
+            trim_silence: None,
+            meter_rate_hz: 20,
+            input_health: InputHealthConfig::default(),
+            stream: StreamRequest::default(),
+            reconnect: None,
+        }
+    }
+}
+
+/// Settings that apply to a single recording rather than the session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordingOptions {
+    /// Stop after exactly this much output audio, finalize the WAV and emit
+    /// `RecordingAutoStopped(MaxDuration)`.
+    pub max_duration: Option<Duration>,
+    /// Split a file recording into `<id>_0001.wav`, `<id>_0002.wav`, ...
+    /// Each segment is a standalone file and is announced with
+    /// `SegmentCompleted` once finalized.
+    pub segment: Option<SegmentLimit>,
+    /// Container and codec, WAV unless set. The target's file name is used
+    /// as given, so pick a matching extension.
+    pub format: OutputFormat,
+}
+
+impl RecordingOptions {
+    /// Where a recording of `target` with these options ends up.
+    pub fn output(&self, target: &RecordingTarget) -> RecordingOutput {
+        match (target, self.segment) {
+            (RecordingTarget::File(filename), Some(_)) => {
+                RecordingOutput::Segments(filename.clone())
+            }
+            _ => target.output(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum RecordingState {
     Idle,
     Initialized,
+    /// Waiting for speech before writing, see `AudioCommand::ArmRecording`.
+    Armed,
     Recording,
     Paused,
     Error(String),
@@ -31,306 +129,720 @@ pub enum AudioCommand {
     EnumerateRecordingDevices,
     InitRecordingSession(UserRecordingSessionConfig),
     CloseRecordingSession,
-    StartRecording(String),
+    StartRecording(RecordingTarget, RecordingOptions),
+    /// Opens the target but only starts writing once speech is detected.
+    ArmRecording(RecordingTarget, RecordingOptions),
     StopRecording,
-    CancelRecording(String),
+    CancelRecording,
+    PauseRecording,
+    ResumeRecording,
+    GetState,
+}
+
+/// Snapshot of the audio thread, as returned by `AudioCommand::GetState`.
+#[derive(Debug, Clone)]
+pub struct RecorderStatus {
+    pub state: RecordingState,
+    pub device_name: Option<String>,
+    pub device_id: Option<DeviceId>,
+    /// Format being written, once a session is initialized. While a
+    /// recording is active the rate is the one it is encoded at, e.g.
+    /// 48 kHz for Opus.
+    pub spec: Option<hound::WavSpec>,
+    pub output: Option<RecordingOutput>,
+    /// Output frames written to the current recording.
+    pub recorded_frames: u64,
+    pub session_started_at: Option<SystemTime>,
 }
 
 #[derive(Debug)]
 pub enum AudioResponse {
-    RecordingDeviceList(Vec<String>),
+    RecordingDeviceList(Vec<InputDeviceInfo>),
+    /// The input stream `InitRecordingSession` ended up opening.
+    SessionInitialized(NegotiatedStream),
+    State(Box<RecorderStatus>),
+    RecordingStopped(StoppedRecording),
     Error(String),
     Success(String),
 }
 
+/// Result of `AudioCommand::StopRecording`.
+#[derive(Debug)]
+pub struct StoppedRecording {
+    /// The finished bytes of an in-memory recording. `None` when it was
+    /// written to a file or a caller-supplied sink.
+    pub data: Option<Vec<u8>>,
+    /// Where the recording ended up.
+    pub output: RecordingOutput,
+    pub format: OutputFormat,
+    pub input_stats: InputStats,
+    /// The files a segmented recording was split into, in order.
+    pub segments: Vec<CompletedSegment>,
+}
+
+/// Correlates a response with the request that caused it.
+pub type RequestId = u64;
+
+#[derive(Debug)]
+pub struct AudioRequest {
+    pub id: RequestId,
+    pub command: AudioCommand,
+}
+
+#[derive(Debug)]
+pub struct AudioReply {
+    pub id: RequestId,
+    pub response: AudioResponse,
+}
+
 struct RecordingSessionSettings {
-    device_name: String,
-    bits_per_sample: u16,
+    device: DeviceId,
+    started_at: SystemTime,
+    /// Kept so the stream can be reopened after the device is lost.
+    config: UserRecordingSessionConfig,
 }
 
 struct RecordingSession {
     settings: RecordingSessionSettings,
-    stream: Stream,
+    /// `None` once a lost stream has been closed for reconnecting.
+    stream: Option<Box<dyn InputStream>>,
     spec: hound::WavSpec,
+    capture: Arc<Mutex<Capture>>,
+    /// The stream as opened. A reconnect asks for the same config.
+    negotiated: NegotiatedStream,
+    recovery: Recovery,
 }
 
-pub fn spawn_audio_thread(
-    response_tx: mpsc::Sender<AudioResponse>,
-) -> Result<mpsc::Sender<AudioCommand>, SendError<AudioCommand>> {
-    let (tx, rx) = mpsc::channel();
+/// How often the audio thread checks on the device while idle, when the
+/// session reconnects after losing it.
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What the audio thread does about a lost input device.
+enum Recovery {
+    /// Reconnecting is disabled or was given up.
+    Off,
+    /// The device is fine; check on it every `DEVICE_CHECK_INTERVAL`.
+    Watching,
+    Reconnecting(Backoff),
+}
+
+/// An input stream that is built and playing, with the capture it feeds.
+struct OpenedStream {
+    stream: Box<dyn InputStream>,
+    spec: hound::WavSpec,
+    capture: Arc<Mutex<Capture>>,
+    negotiated: NegotiatedStream,
+}
 
-    std::thread::spawn(move || -> Result<(), SendError<AudioResponse>> {
-        let host = cpal::default_host();
+/// Negotiates a config for `device` from `request`, then builds and starts
+/// its input stream.
+fn open_stream(
+    backend: &dyn AudioBackend,
+    device: &DeviceId,
+    recording_session_config: &UserRecordingSessionConfig,
+    request: &StreamRequest,
+    events: &EventBus,
+    meters: &EventBus<MeterReading>,
+    chunks: &ChunkSubscribers,
+) -> Result<OpenedStream, String> {
+    let default_config = backend
+        .default_input_config(device)
+        .map_err(|e| e.to_string())?;
+    let supported = if request.keeps_default_config() {
+        Vec::new()
+    } else {
+        backend
+            .supported_input_configs(device)
+            .map_err(|e| e.to_string())?
+    };
+    let (config, buffer_size) = stream_config::negotiate(request, &default_config, &supported)?;
+    debug!("Stream config: {:?}", config);
+
+    // Samples are converted from the device's native format to the
+    // requested encoding in the input callback.
+    recording_session_config
+        .channel_policy
+        .validate(config.channels())?;
+
+    let spec = hound::WavSpec {
+        channels: recording_session_config
+            .channel_policy
+            .output_channels(config.channels()),
+        sample_rate: recording_session_config
+            .target_sample_rate
+            .unwrap_or(config.sample_rate().0),
+        bits_per_sample: recording_session_config.bits_per_sample,
+        sample_format: recording_session_config.sample_format,
+    };
+
+    // The backend runs the input stream on its own thread.
+    let capture = Arc::new(Mutex::new(Capture::new(
+        &config,
+        recording_session_config,
+        events.clone(),
+        meters.clone(),
+        chunks.clone(),
+    )));
+    let capture_clone = Arc::clone(&capture);
+    let write_events = events.clone();
+    let on_data = Box::new(move |data: InputBuffer<'_>| {
+        if let Err(e) = capture_clone.lock().unwrap().process(data) {
+            write_events.emit(RecorderEvent::StreamError(format!(
+                "Failed to write samples: {}",
+                e
+            )));
+        }
+    });
 
-        let writer = Arc::new(Mutex::new(None::<hound::WavWriter<BufWriter<File>>>));
+    let events_clone = events.clone();
+    let lost_capture = Arc::clone(&capture);
+    let mut lost = false;
+    let on_error = Box::new(move |err| {
+        // A dead stream can keep reporting the same error; the device is
+        // only lost once.
+        if lost {
+            return;
+        }
+        let event = RecorderEvent::from_stream_error(err);
+        lost = matches!(event, RecorderEvent::DeviceLost);
+        events_clone.emit(event);
+        if lost {
+            lost_capture.lock().unwrap().device_lost();
+        }
+    });
 
-        let mut current_recording_session: Option<RecordingSession> = None;
+    let stream = backend
+        .build_input_stream(device, &config, buffer_size, on_data, on_error)
+        .map_err(|e| e.to_string())?;
+    stream.play().map_err(|e| e.to_string())?;
+
+    Ok(OpenedStream {
+        stream,
+        spec,
+        capture,
+        negotiated: NegotiatedStream {
+            device_id: device.clone(),
+            channels: config.channels(),
+            sample_rate: config.sample_rate().0,
+            sample_format: config.sample_format(),
+            buffer_size,
+        },
+    })
+}
 
-        while let Ok(cmd) = rx.recv() {
-            match cmd {
-                AudioCommand::EnumerateRecordingDevices => {
-                    let devices = host
-                        .input_devices()
-                        .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
-                        .unwrap_or_else(|e| {
-                            let _ = response_tx.send(AudioResponse::Error(e.to_string()));
-                            vec![]
-                        });
-                    response_tx.send(AudioResponse::RecordingDeviceList(devices))?;
-                }
-                AudioCommand::InitRecordingSession(recording_session_config) => {
-                    if current_recording_session.is_some() {
-                        response_tx.send(AudioResponse::Error(
-                            "Stream already initialized".to_string(),
-                        ))?;
-                        continue;
+impl RecordingSession {
+    fn new(device: DeviceId, config: UserRecordingSessionConfig, opened: OpenedStream) -> Self {
+        Self {
+            recovery: match config.reconnect {
+                Some(_) => Recovery::Watching,
+                None => Recovery::Off,
+            },
+            settings: RecordingSessionSettings {
+                device,
+                started_at: SystemTime::now(),
+                config,
+            },
+            stream: Some(opened.stream),
+            spec: opened.spec,
+            capture: opened.capture,
+            negotiated: opened.negotiated,
+        }
+    }
+
+    /// How long the audio thread may wait for a command before it has to
+    /// check on the device again. `None` means indefinitely.
+    fn recovery_wait(&self) -> Option<Duration> {
+        match &self.recovery {
+            Recovery::Off => None,
+            Recovery::Watching => Some(DEVICE_CHECK_INTERVAL),
+            Recovery::Reconnecting(backoff) => Some(backoff.wait()),
+        }
+    }
+
+    /// Starts reconnecting once the device is lost, and makes the next
+    /// attempt when it is due.
+    fn recover(
+        &mut self,
+        backend: &dyn AudioBackend,
+        events: &EventBus,
+        meters: &EventBus<MeterReading>,
+        chunks: &ChunkSubscribers,
+    ) {
+        match &self.recovery {
+            Recovery::Watching => {
+                if let Some(reconnect) = &self.settings.config.reconnect {
+                    if self.capture.lock().unwrap().is_device_lost() {
+                        self.recovery = Recovery::Reconnecting(Backoff::new(reconnect));
                     }
+                }
+            }
+            Recovery::Reconnecting(backoff) if backoff.wait().is_zero() => {
+                self.reconnect(backend, events, meters, chunks)
+            }
+            _ => {}
+        }
+    }
+
+    /// Tries the lost device again, then the default device if configured.
+    /// A segmented recording that was interrupted continues in its next
+    /// segment.
+    fn reconnect(
+        &mut self,
+        backend: &dyn AudioBackend,
+        events: &EventBus,
+        meters: &EventBus<MeterReading>,
+        chunks: &ChunkSubscribers,
+    ) {
+        let Recovery::Reconnecting(backoff) = &mut self.recovery else {
+            return;
+        };
+        let attempt = backoff.attempt();
+        let mut devices = vec![self.settings.device.clone()];
+        if backoff.config().fallback_to_default {
+            let default = backend
+                .input_devices()
+                .ok()
+                .and_then(|devices| devices.into_iter().find(|d| d.is_default));
+            if let Some(default) = default.filter(|d| d.id != self.settings.device) {
+                devices.push(default.id);
+            }
+        }
+        let request = StreamRequest {
+            sample_rate: Some(self.negotiated.sample_rate),
+            channels: Some(self.negotiated.channels),
+            sample_format: Some(self.negotiated.sample_format),
+            buffer_size: self.negotiated.buffer_size,
+        };
+
+        // The dead stream may still hold the device, and some hosts (ALSA)
+        // refuse to open it again until it is closed.
+        self.stream = None;
+
+        let mut error = String::new();
+        for device in devices {
+            events.emit(RecorderEvent::Reconnecting {
+                attempt,
+                device: device.clone(),
+            });
+            match open_stream(
+                backend,
+                &device,
+                &self.settings.config,
+                &request,
+                events,
+                meters,
+                chunks,
+            ) {
+                Ok(opened) => {
+                    let continued_in = opened
+                        .capture
+                        .lock()
+                        .unwrap()
+                        .take_over(&mut self.capture.lock().unwrap(), opened.spec);
+                    self.stream = Some(opened.stream);
+                    self.spec = opened.spec;
+                    self.capture = opened.capture;
+                    self.negotiated = opened.negotiated;
+                    self.settings.device = device.clone();
+                    self.recovery = Recovery::Watching;
+                    events.emit(RecorderEvent::Reconnected {
+                        device,
+                        continued_in,
+                    });
+                    return;
+                }
+                Err(e) => error = e,
+            }
+        }
 
-                    let device = match host.input_devices() {
-                        Ok(devices) => {
-                            let device_result = devices
-                                .into_iter()
-                                .find(|d| matches!(d.name(), Ok(name) if name == recording_session_config.device_name));
-
-                            match device_result {
-                                Some(device) => device,
-                                None => {
-                                    let _ = response_tx
-                                        .send(AudioResponse::Error("Device not found".to_string()));
-                                    continue;
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let _ = response_tx.send(AudioResponse::Error(e.to_string()));
-                            continue;
-                        }
-                    };
+        if !backoff.failed() {
+            events.emit(RecorderEvent::ReconnectFailed {
+                attempts: backoff.attempts(),
+                error,
+            });
+            self.capture.lock().unwrap().abandon_continuation();
+            self.recovery = Recovery::Off;
+        }
+    }
+
+    /// Opens a writer for `target`. Armed recordings wait for speech before
+    /// writing.
+    fn start_recording(
+        &self,
+        target: RecordingTarget,
+        options: &RecordingOptions,
+        armed: bool,
+    ) -> Result<(), String> {
+        options.format.validate(&self.spec)?;
+        if self.settings.config.trim_silence.is_some() {
+            if matches!(target, RecordingTarget::Sink(_)) {
+                return Err("Silence trimming needs a file or in-memory target".to_string());
+            }
+            if options.format != OutputFormat::Wav {
+                return Err("Silence trimming is only supported for WAV output".to_string());
+            }
+        }
+        if options.segment.is_some() {
+            if !matches!(target, RecordingTarget::File(_)) {
+                return Err("Segmented recording needs a file target".to_string());
+            }
+            if self.settings.config.trim_silence.is_some() {
+                return Err(
+                    "Silence trimming is not supported for segmented recordings".to_string()
+                );
+            }
+        }
+        if matches!(options.segment, Some(SegmentLimit::Bytes(_)))
+            && options.format != OutputFormat::Wav
+        {
+            return Err("Size-limited segments are only supported for WAV output".to_string());
+        }
+        let mut capture = self
+            .capture
+            .lock()
+            .map_err(|e| format!("Failed to acquire lock: {}", e))?;
+        if let RecordingState::Error(e) = capture.state() {
+            return Err(e);
+        }
+        // Replacing the active recording would drop its encoder without
+        // finalizing it.
+        if capture.active().is_some() {
+            return Err(
+                "A recording is already in progress, call stop_recording or cancel_recording"
+                    .to_string(),
+            );
+        }
+        // Starting over would throw away a recording that stopped on its
+        // own, and leave its file behind.
+        if capture.has_finished() {
+            return Err(
+                "Previous recording not collected, call stop_recording or cancel_recording"
+                    .to_string(),
+            );
+        }
+        let started = if armed {
+            capture.arm(target, self.spec, options)
+        } else {
+            capture.start(target, self.spec, options)
+        };
+        started.map_err(|e| format!("Failed to create writer: {}", e))
+    }
+
+    /// Finalizes the active recording, or collects one the input callback
+    /// already stopped on its own. In-memory recordings return their bytes.
+    fn stop_recording(&self) -> Result<StoppedRecording, String> {
+        let mut capture = self
+            .capture
+            .lock()
+            .map_err(|e| format!("Failed to acquire lock: {}", e))?;
+        let finished = capture.take_finished();
+        let active = match finished {
+            Some(_) => None,
+            None => capture.take(),
+        };
+        // Finalizing and trimming rewrite the file; the input callback must
+        // not wait on the lock meanwhile.
+        drop(capture);
+        let finished = match (finished, active) {
+            (Some(finished), _) => finished,
+            (None, Some(active)) => active.finish(),
+            (None, None) => return Err("No active recording to stop".to_string()),
+        };
+        let data = finished
+            .result
+            .map_err(|e| format!("Failed to finalize recording: {}", e))?;
+
+        let data = match (&self.settings.config.trim_silence, &finished.output, data) {
+            (None, _, data) => data,
+            (Some(config), RecordingOutput::File(filename), _) => {
+                trim::trim_file(filename, config)
+                    .map_err(|e| format!("Failed to trim silence: {}", e))?;
+                None
+            }
+            (Some(config), _, Some(data)) => Some(
+                trim::trim_bytes(data, config)
+                    .map_err(|e| format!("Failed to trim silence: {}", e))?,
+            ),
+            (Some(_), _, None) => None,
+        };
+        Ok(StoppedRecording {
+            data,
+            output: finished.output,
+            format: finished.format,
+            input_stats: finished.input_stats,
+            segments: finished.segments,
+        })
+    }
+
+    /// Drops the active or auto-stopped recording without finalizing it and
+    /// returns the file that should be deleted, if any. Segments that were
+    /// already announced as completed are kept.
+    fn discard_recording(&self) -> Result<Option<String>, String> {
+        let mut capture = self
+            .capture
+            .lock()
+            .map_err(|e| format!("Failed to acquire lock: {}", e))?;
+        if let Some(finished) = capture.take_finished() {
+            return Ok(match finished.output {
+                RecordingOutput::File(filename) => Some(filename),
+                _ => None,
+            });
+        }
+        capture
+            .take()
+            .map(|active| active.current_file())
+            .ok_or_else(|| "No active recording to cancel".to_string())
+    }
+
+    /// Stops the stream and finalizes a recording still in progress, so a
+    /// file target is left complete on disk rather than truncated.
+    fn close(self) {
+        drop(self.stream);
+        let active = self.capture.lock().unwrap().take();
+        if let Some(active) = active {
+            if let Err(e) = active.finish().result {
+                warn!("Failed to finalize recording on close: {}", e);
+            }
+        }
+    }
+
+    fn status(&self) -> RecorderStatus {
+        let capture = self.capture.lock().unwrap();
+        let active = capture.active();
+        RecorderStatus {
+            state: capture.state(),
+            device_name: Some(self.settings.device.name.clone()),
+            device_id: Some(self.settings.device.clone()),
+            spec: Some(hound::WavSpec {
+                sample_rate: active.map_or(self.spec.sample_rate, |a| {
+                    a.format().sample_rate(&self.spec)
+                }),
+                ..self.spec
+            }),
+            output: active.map(|a| a.output().clone()),
+            recorded_frames: active.map_or(0, |a| a.frames_written()),
+            session_started_at: Some(self.settings.started_at),
+        }
+    }
+}
 
-                    let default_device_config = match device.default_input_config() {
-                        Ok(config) => config,
-                        Err(e) => {
-                            let _ = response_tx.send(AudioResponse::Error(e.to_string()));
-                            continue;
-                        }
-                    };
-
-                    let config: cpal::SupportedStreamConfig = default_device_config.into();
-                    println!("Stream config: {:?}", config);
-
-                    let bytes_per_sample = config.sample_format().sample_size();
-                    let spec = hound::WavSpec {
-                        channels: config.channels(),
-                        sample_rate: config.sample_rate().0,
-                        bits_per_sample: (bytes_per_sample * 8) as u16,
-                        sample_format: match config.sample_format() {
-                            cpal::SampleFormat::I8
-                            | cpal::SampleFormat::I16
-                            | cpal::SampleFormat::I32 => hound::SampleFormat::Int,
-                            cpal::SampleFormat::F32 => hound::SampleFormat::Float,
-                            _ => {
-                                response_tx.send(AudioResponse::Error(format!(
-                                    "Unsupported sample format: {:?}",
-                                    config.sample_format()
-                                )))?;
-                                continue;
-                            }
-                        },
-                    };
-
-                    // Run the input stream on a separate thread.
-                    let writer_clone = Arc::clone(&writer);
-
-                    let response_tx_clone = response_tx.clone();
-
-                    fn build_input_stream<T>(
-                        device: &cpal::Device,
-                        config: &cpal::StreamConfig,
-                        writer: Arc<Mutex<Option<hound::WavWriter<BufWriter<File>>>>>,
-                        error_callback: impl FnMut(cpal::StreamError) + Send + 'static,
-                    ) -> Result<cpal::Stream, cpal::BuildStreamError>
-                    where
-                        T: Sample + hound::Sample + cpal::SizedSample,
-                    {
-                        device.build_input_stream(
-                            config,
-                            move |data: &[T], _: &_| {
-                                if let Some(writer) = &mut *writer.lock().unwrap() {
-                                    for &sample in data {
-                                        writer.write_sample(sample).unwrap();
-                                    }
-                                }
-                            },
-                            error_callback,
-                            None,
-                        )
-                    }
+pub type AudioThreadHandle = JoinHandle<Result<(), SendError<AudioReply>>>;
+
+pub fn spawn_audio_thread(
+    backend: Box<dyn AudioBackend + Send>,
+    response_tx: mpsc::Sender<AudioReply>,
+    events: EventBus,
+    meters: EventBus<MeterReading>,
+    chunks: ChunkSubscribers,
+) -> std::io::Result<(mpsc::Sender<AudioRequest>, AudioThreadHandle)> {
+    let (tx, rx) = mpsc::channel();
 
-                    let err_fn = move |err| {
-                        let _ = response_tx_clone
-                            .send(AudioResponse::Error(format!("Error in stream: {}", err)));
-                    };
+    let handle = std::thread::Builder::new()
+        .name("audio".to_string())
+        .spawn(move || -> Result<(), SendError<AudioReply>> {
+            let mut current_recording_session: Option<RecordingSession> = None;
 
-                    let stream = match config.sample_format() {
-                        cpal::SampleFormat::I8 => {
-                            build_input_stream::<i8>(&device, &config.into(), writer_clone, err_fn)
-                        }
-                        cpal::SampleFormat::I16 => {
-                            build_input_stream::<i16>(&device, &config.into(), writer_clone, err_fn)
-                        }
-                        cpal::SampleFormat::I32 => {
-                            build_input_stream::<i32>(&device, &config.into(), writer_clone, err_fn)
-                        }
-                        cpal::SampleFormat::F32 => {
-                            build_input_stream::<f32>(&device, &config.into(), writer_clone, err_fn)
-                        }
-                        _ => {
-                            response_tx.send(AudioResponse::Error(format!(
-                                "Unsupported sample format: {:?}",
-                                config.sample_format()
-                            )))?;
+            loop {
+                // Checked after every command as well as on timeouts, so a
+                // caller polling e.g. `GetState` can't hold off a reconnect.
+                if let Some(session) = &mut current_recording_session {
+                    session.recover(&*backend, &events, &meters, &chunks);
+                }
+                let wait = current_recording_session
+                    .as_ref()
+                    .and_then(RecordingSession::recovery_wait);
+                let AudioRequest { id, command } = match wait {
+                    Some(wait) => match rx.recv_timeout(wait) {
+                        Ok(request) => request,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    },
+                    None => match rx.recv() {
+                        Ok(request) => request,
+                        Err(_) => break,
+                    },
+                };
+                let respond = |response| response_tx.send(AudioReply { id, response });
+
+                match command {
+                    AudioCommand::EnumerateRecordingDevices => match backend.input_devices() {
+                        Ok(devices) => respond(AudioResponse::RecordingDeviceList(devices))?,
+                        Err(e) => respond(AudioResponse::Error(e.to_string()))?,
+                    },
+                    AudioCommand::InitRecordingSession(recording_session_config) => {
+                        if current_recording_session.is_some() {
+                            respond(AudioResponse::Error(
+                                "Stream already initialized".to_string(),
+                            ))?;
                             continue;
                         }
-                    };
-
-                    let stream = match stream {
-                        Ok(stream) => stream,
-                        Err(e) => {
-                            response_tx.send(AudioResponse::Error(format!(
-                                "Failed to build stream: {}",
-                                e
-                            )))?;
+
+                        if let Err(e) = sample::validate_output_format(
+                            recording_session_config.bits_per_sample,
+                            recording_session_config.sample_format,
+                        ) {
+                            respond(AudioResponse::Error(e))?;
                             continue;
                         }
-                    };
-
-                    if let Err(e) = stream.play() {
-                        response_tx.send(AudioResponse::Error(format!(
-                            "Failed to start stream: {}",
-                            e
-                        )))?;
-                        continue;
-                    }
-
-                    current_recording_session = Some(RecordingSession {
-                        settings: RecordingSessionSettings {
-                            device_name: recording_session_config.device_name,
-                            bits_per_sample: recording_session_config.bits_per_sample,
-                        },
-                        stream: stream,
-                        spec: spec,
-                    });
 
-                    response_tx.send(AudioResponse::Success(
-                        "Recording session initialized".to_string(),
-                    ))?;
-                }
-                AudioCommand::StartRecording(filename) => {
-                    let recording_session = match &current_recording_session {
-                        None => {
-                            response_tx.send(AudioResponse::Error(
-                                "Recording session not initialized".to_string(),
-                            ))?;
-                            continue;
+                        let device =
+                            match backend.resolve_device(&recording_session_config.device_name) {
+                                Ok(device) => device,
+                                Err(e) => {
+                                    respond(AudioResponse::Error(e.to_string()))?;
+                                    continue;
+                                }
+                            };
+
+                        match open_stream(
+                            &*backend,
+                            &device,
+                            &recording_session_config,
+                            &recording_session_config.stream,
+                            &events,
+                            &meters,
+                            &chunks,
+                        ) {
+                            Ok(opened) => {
+                                let negotiated = opened.negotiated.clone();
+                                current_recording_session = Some(RecordingSession::new(
+                                    device,
+                                    recording_session_config,
+                                    opened,
+                                ));
+                                respond(AudioResponse::SessionInitialized(negotiated))?;
+                            }
+                            Err(e) => respond(AudioResponse::Error(e))?,
                         }
-                        Some(session) => session,
-                    };
-
-                    let new_writer =
-                        match hound::WavWriter::create(&filename, recording_session.spec) {
-                            Ok(writer) => writer,
-                            Err(e) => {
-                                response_tx.send(AudioResponse::Error(format!(
-                                    "Failed to create WAV writer: {}",
-                                    e
-                                )))?;
-                                continue;
+                    }
+                    AudioCommand::StartRecording(target, options) => {
+                        let started = current_recording_session
+                            .as_ref()
+                            .ok_or_else(|| "Recording session not initialized".to_string())
+                            .and_then(|session| session.start_recording(target, &options, false));
+                        match started {
+                            Ok(()) => {
+                                respond(AudioResponse::Success("Recording started".to_string()))?
                             }
-                        };
-
-                    *writer.lock().unwrap() = Some(new_writer);
-                    response_tx.send(AudioResponse::Success("Recording started".to_string()))?;
-                }
-                AudioCommand::StopRecording => {
-                    let wav_writer_result = writer
-                        .lock()
-                        .map_err(|e| format!("Failed to acquire lock: {}", e))
-                        .and_then(|mut guard| {
-                            guard
-                                .take()
-                                .ok_or_else(|| "No active recording to stop".to_string())
-                        });
-
-                    match wav_writer_result {
-                        Ok(writer) => {
-                            drop(writer);
-                            response_tx
-                                .send(AudioResponse::Success("Recording stopped".to_string()))?;
+                            Err(err) => respond(AudioResponse::Error(err))?,
                         }
-                        Err(err) => {
-                            response_tx.send(AudioResponse::Error(err))?;
+                    }
+                    AudioCommand::ArmRecording(target, options) => {
+                        let armed = current_recording_session
+                            .as_ref()
+                            .ok_or_else(|| "Recording session not initialized".to_string())
+                            .and_then(|session| session.start_recording(target, &options, true));
+                        match armed {
+                            Ok(()) => respond(AudioResponse::Success(
+                                "Recording armed, waiting for speech".to_string(),
+                            ))?,
+                            Err(err) => respond(AudioResponse::Error(err))?,
                         }
                     }
-                }
-                AudioCommand::CancelRecording(filename) => {
-                    let wav_writer_result = writer
-                        .lock()
-                        .map_err(|e| format!("Failed to acquire lock: {}", e))
-                        .and_then(|mut guard| {
-                            guard
-                                .take()
-                                .ok_or_else(|| "No active recording to cancel".to_string())
-                        });
-
-                    match wav_writer_result {
-                        Ok(writer) => {
-                            drop(writer);
-                            match std::fs::remove_file(&filename) {
-                                Ok(_) => response_tx.send(AudioResponse::Success(
-                                    "Recording cancelled and file deleted".to_string(),
+                    AudioCommand::StopRecording => {
+                        let stopped = current_recording_session
+                            .as_ref()
+                            .ok_or_else(|| "No active recording to stop".to_string())
+                            .and_then(|session| session.stop_recording());
+
+                        match stopped {
+                            Ok(stopped) => respond(AudioResponse::RecordingStopped(stopped))?,
+                            Err(err) => {
+                                respond(AudioResponse::Error(err))?;
+                            }
+                        }
+                    }
+                    AudioCommand::CancelRecording => {
+                        let discarded = current_recording_session
+                            .as_ref()
+                            .ok_or_else(|| "No active recording to cancel".to_string())
+                            .and_then(|session| session.discard_recording());
+
+                        match discarded {
+                            Ok(file) => match file {
+                                Some(filename) => match std::fs::remove_file(&filename) {
+                                    Ok(_) => respond(AudioResponse::Success(
+                                        "Recording cancelled and file deleted".to_string(),
+                                    ))?,
+                                    Err(e) => respond(AudioResponse::Error(format!(
+                                        "Failed to delete partial recording: {}",
+                                        e
+                                    )))?,
+                                },
+                                None => respond(AudioResponse::Success(
+                                    "Recording cancelled".to_string(),
                                 ))?,
-                                Err(e) => response_tx.send(AudioResponse::Error(format!(
-                                    "Failed to delete partial recording: {}",
-                                    e
-                                )))?,
+                            },
+                            Err(err) => {
+                                respond(AudioResponse::Error(err))?;
                             }
                         }
-                        Err(err) => {
-                            response_tx.send(AudioResponse::Error(err))?;
+                    }
+                    AudioCommand::PauseRecording => {
+                        let result = match &current_recording_session {
+                            Some(session) => session.capture.lock().unwrap().pause(),
+                            None => Err("No active recording to pause".to_string()),
+                        };
+                        match result {
+                            Ok(()) => {
+                                respond(AudioResponse::Success("Recording paused".to_string()))?
+                            }
+                            Err(err) => respond(AudioResponse::Error(err))?,
                         }
                     }
-                }
-                AudioCommand::CloseRecordingSession => {
-                    if let Some(session) = current_recording_session.take() {
-                        drop(session.stream);
-                        response_tx.send(AudioResponse::Success(
-                            "Recording session closed successfully".to_string(),
-                        ))?;
-                    } else {
-                        response_tx.send(AudioResponse::Error(
-                            "No active recording session to close".to_string(),
-                        ))?;
+                    AudioCommand::ResumeRecording => {
+                        let result = match &current_recording_session {
+                            Some(session) => session.capture.lock().unwrap().resume(),
+                            None => Err("No active recording to resume".to_string()),
+                        };
+                        match result {
+                            Ok(()) => {
+                                respond(AudioResponse::Success("Recording resumed".to_string()))?
+                            }
+                            Err(err) => respond(AudioResponse::Error(err))?,
+                        }
                     }
-                }
-                AudioCommand::CloseThread => {
-                    // Clean up any active recording session
-                    if let Some(session) = current_recording_session.take() {
-                        drop(session.stream);
+                    AudioCommand::GetState => {
+                        let status = match &current_recording_session {
+                            Some(session) => session.status(),
+                            None => RecorderStatus {
+                                state: RecordingState::Idle,
+                                device_name: None,
+                                device_id: None,
+                                spec: None,
+                                output: None,
+                                recorded_frames: 0,
+                                session_started_at: None,
+                            },
+                        };
+                        respond(AudioResponse::State(Box::new(status)))?;
                     }
-
-                    // Clean up any active writer
-                    if let Ok(mut guard) = writer.lock() {
-                        *guard = None;
+                    AudioCommand::CloseRecordingSession => {
+                        if let Some(session) = current_recording_session.take() {
+                            session.close();
+                            respond(AudioResponse::Success(
+                                "Recording session closed successfully".to_string(),
+                            ))?;
+                        } else {
+                            respond(AudioResponse::Error(
+                                "No active recording session to close".to_string(),
+                            ))?;
+                        }
                     }
+                    AudioCommand::CloseThread => {
+                        // Clean up any active recording session and its writer
+                        if let Some(session) = current_recording_session.take() {
+                            session.close();
+                        }
 
-                    response_tx.send(AudioResponse::Success("Thread closed".to_string()))?;
-                    break;
+                        respond(AudioResponse::Success("Thread closed".to_string()))?;
+                        break;
+                    }
                 }
             }
-        }
 
-        Ok(())
-    });
+            Ok(())
+        })?;
 
-    Ok(tx)
+    Ok((tx, handle))
 }
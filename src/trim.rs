@@ -0,0 +1,225 @@
+use std::io::{Cursor, Read, Seek, Write};
+use std::time::Duration;
+
+/// Settings for trimming leading and trailing silence when a recording stops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrimConfig {
+    /// Samples quieter than this many dBFS count as silence.
+    pub threshold_dbfs: f32,
+    /// Silence kept before the first and after the last loud sample.
+    pub margin: Duration,
+}
+
+impl Default for TrimConfig {
+    fn default() -> Self {
+        Self {
+            threshold_dbfs: -50.0,
+            margin: Duration::from_millis(200),
+        }
+    }
+}
+
+/// Trims the WAV file at `path` in place. The file is only rewritten if
+/// there is something to trim.
+pub(crate) fn trim_file(path: &str, config: &TrimConfig) -> hound::Result<()> {
+    let range = kept_range(hound::WavReader::open(path)?, config)?;
+    let reader = hound::WavReader::open(path)?;
+    if range == (0, reader.duration()) {
+        return Ok(());
+    }
+    let temp_path = format!("{}.trim", path);
+    let writer = hound::WavWriter::create(&temp_path, reader.spec())?;
+    copy_range(reader, writer, range)?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// Trims an in-memory WAV and returns the new bytes.
+pub(crate) fn trim_bytes(bytes: Vec<u8>, config: &TrimConfig) -> hound::Result<Vec<u8>> {
+    let range = kept_range(hound::WavReader::new(Cursor::new(&bytes))?, config)?;
+    let reader = hound::WavReader::new(Cursor::new(&bytes))?;
+    if range == (0, reader.duration()) {
+        return Ok(bytes);
+    }
+    let mut trimmed = Cursor::new(Vec::new());
+    let writer = hound::WavWriter::new(&mut trimmed, reader.spec())?;
+    copy_range(reader, writer, range)?;
+    Ok(trimmed.into_inner())
+}
+
+/// Frames `[start, end)` to keep: from `margin` before the first loud frame
+/// to `margin` after the last one. A recording with no loud frames is
+/// trimmed to nothing.
+fn kept_range<R: Read>(
+    mut reader: hound::WavReader<R>,
+    config: &TrimConfig,
+) -> hound::Result<(u32, u32)> {
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as u32;
+    let threshold = 10f32.powf(config.threshold_dbfs / 20.0);
+    let total_frames = reader.duration();
+
+    let mut first_loud = None;
+    let mut last_loud = 0;
+    let mut check = |index: u32, level: f32| {
+        if level >= threshold {
+            let frame = index / channels;
+            first_loud.get_or_insert(frame);
+            last_loud = frame;
+        }
+    };
+    match spec.sample_format {
+        hound::SampleFormat::Float => {
+            for (index, sample) in reader.samples::<f32>().enumerate() {
+                check(index as u32, sample?.abs());
+            }
+        }
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            for (index, sample) in reader.samples::<i32>().enumerate() {
+                check(index as u32, (sample? as f32 / scale).abs());
+            }
+        }
+    }
+
+    let Some(first_loud) = first_loud else {
+        return Ok((0, 0));
+    };
+    let margin = (config.margin.as_secs_f64() * spec.sample_rate as f64).round() as u32;
+    Ok((
+        first_loud.saturating_sub(margin),
+        last_loud.saturating_add(margin + 1).min(total_frames),
+    ))
+}
+
+fn copy_range<R: Read + Seek, W: Write + Seek>(
+    mut reader: hound::WavReader<R>,
+    mut writer: hound::WavWriter<W>,
+    (start, end): (u32, u32),
+) -> hound::Result<()> {
+    let spec = reader.spec();
+    let samples = (end - start) as usize * spec.channels as usize;
+    reader.seek(start)?;
+    match spec.sample_format {
+        hound::SampleFormat::Float => {
+            for sample in reader.samples::<f32>().take(samples) {
+                writer.write_sample(sample?)?;
+            }
+        }
+        hound::SampleFormat::Int => {
+            for sample in reader.samples::<i32>().take(samples) {
+                writer.write_sample(sample?)?;
+            }
+        }
+    }
+    writer.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    fn spec(sample_format: hound::SampleFormat, bits_per_sample: u16) -> hound::WavSpec {
+        hound::WavSpec {
+            channels: 2,
+            sample_rate: RATE,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+
+    /// Stereo WAV of `lead` silent frames, `loud` frames at half scale on
+    /// the right channel only, then `tail` silent frames.
+    fn wav(spec: hound::WavSpec, lead: usize, loud: usize, tail: usize) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+        let frames = std::iter::repeat_n(0.0, lead)
+            .chain(std::iter::repeat_n(0.5, loud))
+            .chain(std::iter::repeat_n(0.0, tail));
+        let samples: Vec<f32> = frames.flat_map(|right| [0.0, right]).collect();
+        crate::sample::write_normalized(&mut writer, &samples).unwrap();
+        writer.finalize().unwrap();
+        bytes.into_inner()
+    }
+
+    fn config(margin_ms: u64) -> TrimConfig {
+        TrimConfig {
+            threshold_dbfs: -50.0,
+            margin: Duration::from_millis(margin_ms),
+        }
+    }
+
+    fn frames(bytes: &[u8]) -> u32 {
+        hound::WavReader::new(Cursor::new(bytes))
+            .unwrap()
+            .duration()
+    }
+
+    #[test]
+    fn keeps_the_loud_part_plus_margin() {
+        for (format, bits) in [
+            (hound::SampleFormat::Int, 16),
+            (hound::SampleFormat::Int, 24),
+            (hound::SampleFormat::Float, 32),
+        ] {
+            let trimmed = trim_bytes(wav(spec(format, bits), 500, 300, 700), &config(100)).unwrap();
+            assert_eq!(frames(&trimmed), 100 + 300 + 100);
+
+            let mut reader = hound::WavReader::new(Cursor::new(&trimmed)).unwrap();
+            assert_eq!(reader.spec(), spec(format, bits));
+            let loud = match format {
+                hound::SampleFormat::Float => reader
+                    .samples::<f32>()
+                    .filter(|s| *s.as_ref().unwrap() != 0.0)
+                    .count(),
+                hound::SampleFormat::Int => reader
+                    .samples::<i32>()
+                    .filter(|s| *s.as_ref().unwrap() != 0)
+                    .count(),
+            };
+            assert_eq!(loud, 300);
+        }
+    }
+
+    #[test]
+    fn margin_is_clamped_to_the_recording() {
+        let trimmed = trim_bytes(
+            wav(spec(hound::SampleFormat::Int, 16), 50, 300, 20),
+            &config(100),
+        )
+        .unwrap();
+        assert_eq!(frames(&trimmed), 50 + 300 + 20);
+    }
+
+    #[test]
+    fn leaves_untrimmable_recordings_alone() {
+        let bytes = wav(spec(hound::SampleFormat::Int, 16), 0, 300, 0);
+        assert_eq!(trim_bytes(bytes.clone(), &config(0)).unwrap(), bytes);
+    }
+
+    #[test]
+    fn silent_recordings_are_trimmed_to_nothing() {
+        let trimmed = trim_bytes(
+            wav(spec(hound::SampleFormat::Int, 16), 500, 0, 0),
+            &config(100),
+        )
+        .unwrap();
+        assert_eq!(frames(&trimmed), 0);
+    }
+
+    #[test]
+    fn trims_files_in_place() {
+        let path = std::env::temp_dir().join(format!("trim-test-{}.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, wav(spec(hound::SampleFormat::Int, 16), 500, 300, 700)).unwrap();
+
+        trim_file(path, &config(100)).unwrap();
+        let trimmed = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(frames(&trimmed), 500);
+        assert!(!std::path::Path::new(&format!("{}.trim", path)).exists());
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+/// Settings for the energy-based voice activity detector.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VadConfig {
+    /// Blocks whose RMS level reaches this many dBFS count as speech.
+    pub threshold_dbfs: f32,
+    /// How long speech is still considered ongoing after the level drops,
+    /// so short pauses between words are not treated as silence.
+    pub hangover: Duration,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            threshold_dbfs: -40.0,
+            hangover: Duration::from_millis(300),
+        }
+    }
+}
+
+/// RMS level of interleaved samples in dBFS. Silence maps to negative infinity.
+pub fn rms_dbfs(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return f32::NEG_INFINITY;
+    }
+    let mean_square =
+        samples.iter().map(|&s| s as f64 * s as f64).sum::<f64>() / samples.len() as f64;
+    (10.0 * mean_square.log10()) as f32
+}
+
+/// Classifies blocks of audio as speech or silence by their RMS level.
+pub struct VoiceActivityDetector {
+    threshold_dbfs: f32,
+    hangover_frames: u64,
+    /// Frames since the last block above the threshold. `None` until the
+    /// first one.
+    frames_since_speech: Option<u64>,
+}
+
+impl VoiceActivityDetector {
+    pub fn new(config: &VadConfig, sample_rate: u32) -> Self {
+        Self {
+            threshold_dbfs: config.threshold_dbfs,
+            hangover_frames: duration_to_frames(config.hangover, sample_rate),
+            frames_since_speech: None,
+        }
+    }
+
+    /// Feeds one block of interleaved samples and returns whether speech is
+    /// ongoing, hangover included.
+    pub fn process(&mut self, samples: &[f32], channels: u16) -> bool {
+        let frames = (samples.len() / channels.max(1) as usize) as u64;
+        if rms_dbfs(samples) >= self.threshold_dbfs {
+            self.frames_since_speech = Some(0);
+        } else if let Some(since) = &mut self.frames_since_speech {
+            *since = since.saturating_add(frames);
+        }
+        self.is_speech()
+    }
+
+    pub fn is_speech(&self) -> bool {
+        self.frames_since_speech
+            .is_some_and(|since| since <= self.hangover_frames)
+    }
+
+    /// Frames of silence since speech ended, the hangover included. Zero
+    /// while speech is still considered ongoing, so pauses shorter than the
+    /// hangover never count, and zero until speech was heard at all.
+    pub fn silent_frames(&self) -> u64 {
+        match self.frames_since_speech {
+            Some(since) if !self.is_speech() => since,
+            _ => 0,
+        }
+    }
+}
+
+pub(crate) fn duration_to_frames(duration: Duration, sample_rate: u32) -> u64 {
+    (duration.as_secs_f64() * sample_rate as f64).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    fn detector() -> VoiceActivityDetector {
+        VoiceActivityDetector::new(
+            &VadConfig {
+                threshold_dbfs: -40.0,
+                hangover: Duration::from_millis(300),
+            },
+            RATE,
+        )
+    }
+
+    #[test]
+    fn silence_is_counted_from_the_end_of_speech() {
+        let mut vad = detector();
+        assert!(vad.process(&[0.5; 100], 1));
+        assert_eq!(vad.silent_frames(), 0);
+
+        // Within the hangover, speech is still ongoing.
+        assert!(vad.process(&[0.0; 300], 1));
+        assert_eq!(vad.silent_frames(), 0);
+
+        assert!(!vad.process(&[0.0; 100], 1));
+        assert_eq!(vad.silent_frames(), 400);
+
+        assert!(vad.process(&[0.5; 10], 1));
+        assert_eq!(vad.silent_frames(), 0);
+    }
+
+    #[test]
+    fn silence_before_any_speech_is_not_counted() {
+        let mut vad = detector();
+        assert!(!vad.process(&[0.0; 1000], 1));
+        assert_eq!(vad.silent_frames(), 0);
+    }
+
+    #[test]
+    fn counts_frames_not_samples() {
+        let mut vad = detector();
+        vad.process(&[0.5; 2], 2);
+        vad.process(&[0.0; 1000], 2);
+        assert_eq!(vad.silent_frames(), 500);
+    }
+}
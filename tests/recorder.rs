@@ -0,0 +1,1181 @@
+//! End-to-end tests of `Recorder` over the synthetic backend, which paces its
+//! callbacks in real time. Waits poll the recorder rather than sleeping for
+//! fixed amounts, so slow machines only make the tests slower.
+
+use std::io::{Cursor, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::{
+    backend::{Signal, SyntheticBackend, SyntheticDevice},
+    channels::ChannelPolicy,
+    chunks::ChunkConfig,
+    encoder::OutputFormat,
+    events::{AutoStopReason, RecorderEvent},
+    health::{InputHealthConfig, InputWarning},
+    reconnect::ReconnectConfig,
+    recorder::{Recorder, RecorderError, Recording},
+    resample::ResampleQuality,
+    segment::SegmentLimit,
+    sink::{RecordingOutput, RecordingTarget},
+    stream_config::StreamRequest,
+    thread::{RecordingOptions, RecordingState, UserRecordingSessionConfig},
+    vad::VadConfig,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn recorder() -> Recorder {
+    Recorder::with_backend(Box::new(SyntheticBackend::default())).unwrap()
+}
+
+fn session() -> UserRecordingSessionConfig {
+    UserRecordingSessionConfig {
+        bits_per_sample: 16,
+        ..UserRecordingSessionConfig::default()
+    }
+}
+
+fn reconnecting_session() -> UserRecordingSessionConfig {
+    UserRecordingSessionConfig {
+        reconnect: Some(ReconnectConfig {
+            initial_backoff: Duration::from_millis(20),
+            ..ReconnectConfig::default()
+        }),
+        ..session()
+    }
+}
+
+/// A recorder whose only device, a mono 48 kHz "default", hears `silence`
+/// frames of silence, then `tone` frames of a loud sine, then silence for
+/// good. The script starts when the session is initialized.
+fn scripted_recorder(silence: usize, tone: usize) -> Recorder {
+    let samples: Vec<f32> = (0..silence + tone)
+        .map(|frame| match frame.checked_sub(silence) {
+            Some(t) => 0.5 * (t as f32 * 440.0 * std::f32::consts::TAU / 48_000.0).sin(),
+            None => 0.0,
+        })
+        .collect();
+    let device = SyntheticDevice::new("default", Signal::Scripted(samples.into()));
+    Recorder::with_backend(Box::new(SyntheticBackend::new(vec![device]))).unwrap()
+}
+
+/// A recorder whose only device, a stereo 48 kHz "default", hears a
+/// constant 0.5 on the left channel and -0.25 on the right.
+fn stereo_dc_recorder() -> Recorder {
+    let samples: Vec<f32> = std::iter::repeat_n([0.5, -0.25], 96_000)
+        .flatten()
+        .collect();
+    let device = SyntheticDevice {
+        channels: 2,
+        ..SyntheticDevice::new("default", Signal::Scripted(samples.into()))
+    };
+    Recorder::with_backend(Box::new(SyntheticBackend::new(vec![device]))).unwrap()
+}
+
+/// Records into memory until `max_duration` stops the recording on its own.
+fn record_until_limit(
+    session: UserRecordingSessionConfig,
+    format: OutputFormat,
+    max_duration: Duration,
+) -> Recording {
+    let recorder = recorder();
+    let events = recorder.subscribe();
+    recorder.init_recording_session(session).unwrap();
+    recorder
+        .start_recording_with(
+            RecordingTarget::Memory,
+            RecordingOptions {
+                max_duration: Some(max_duration),
+                format,
+                ..RecordingOptions::default()
+            },
+        )
+        .unwrap();
+    wait_for_event(&events, |event| {
+        matches!(
+            event,
+            RecorderEvent::RecordingAutoStopped(AutoStopReason::MaxDuration)
+        )
+    });
+    recorder.stop_recording().unwrap()
+}
+
+/// A caller-supplied sink whose bytes the test can read back.
+#[derive(Clone, Default)]
+struct SharedSink(Arc<Mutex<Cursor<Vec<u8>>>>);
+
+impl SharedSink {
+    fn bytes(&self) -> Vec<u8> {
+        self.0.lock().unwrap().get_ref().clone()
+    }
+}
+
+impl Write for SharedSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SharedSink {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.0.lock().unwrap().seek(pos)
+    }
+}
+
+/// A fresh directory for one test's files.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("recorder-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn files_in(dir: &PathBuf) -> Vec<String> {
+    let mut files: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    files
+}
+
+fn wait_for_frames(recorder: &Recorder, frames: u64) {
+    let deadline = Instant::now() + TIMEOUT;
+    while recorder.get_state().unwrap().recorded_frames < frames {
+        assert!(Instant::now() < deadline, "timed out waiting for audio");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn wait_for_event(events: &Receiver<RecorderEvent>, matches: impl Fn(&RecorderEvent) -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match events.recv_timeout(left) {
+            Ok(event) if matches(&event) => return,
+            Ok(_) => {}
+            Err(_) => panic!("timed out waiting for an event"),
+        }
+    }
+}
+
+fn wav_reader(bytes: &[u8]) -> hound::WavReader<Cursor<&[u8]>> {
+    hound::WavReader::new(Cursor::new(bytes)).unwrap()
+}
+
+#[test]
+fn state_follows_the_session_lifecycle() {
+    let recorder = recorder();
+    assert!(matches!(
+        recorder.get_state().unwrap().state,
+        RecordingState::Idle
+    ));
+
+    let stream = recorder.init_recording_session(session()).unwrap();
+    assert_eq!(stream.channels, 2);
+    assert_eq!(stream.sample_rate, 48_000);
+    let status = recorder.get_state().unwrap();
+    assert!(matches!(status.state, RecordingState::Initialized));
+    assert_eq!(status.device_name.as_deref(), Some("default"));
+    assert_eq!(status.spec.unwrap().bits_per_sample, 16);
+
+    recorder.start_recording_in_memory().unwrap();
+    let status = recorder.get_state().unwrap();
+    assert!(matches!(status.state, RecordingState::Recording));
+    assert_eq!(status.output, Some(RecordingOutput::Memory));
+
+    recorder.stop_recording().unwrap();
+    assert!(matches!(
+        recorder.get_state().unwrap().state,
+        RecordingState::Initialized
+    ));
+
+    recorder.close_recording_session().unwrap();
+    assert!(matches!(
+        recorder.get_state().unwrap().state,
+        RecordingState::Idle
+    ));
+}
+
+#[test]
+fn enumerates_devices_with_their_ids_and_configs() {
+    let backend = SyntheticBackend::default();
+    let faults = backend.faults();
+    let recorder = Recorder::with_backend(Box::new(backend)).unwrap();
+    let devices = recorder.enumerate_recording_devices().unwrap();
+
+    let ids: Vec<&str> = devices.iter().map(|d| d.device_id.as_str()).collect();
+    assert_eq!(
+        ids,
+        [
+            "Synthetic:default#0",
+            "Synthetic:Synthetic Noise#0",
+            "Synthetic:Synthetic Silence#0"
+        ]
+    );
+    let defaults: Vec<bool> = devices.iter().map(|d| d.is_default).collect();
+    assert_eq!(defaults, [true, false, false]);
+    let noise = &devices[1];
+    assert_eq!(noise.label, "Synthetic Noise");
+    let config = noise.default_config.as_ref().unwrap();
+    assert_eq!(config.sample_rate().0, 44_100);
+    assert_eq!(config.sample_format(), cpal::SampleFormat::I16);
+    assert_eq!(noise.supported_configs.len(), 1);
+
+    // The ID selects the device when initializing a session.
+    let stream = recorder
+        .init_recording_session(UserRecordingSessionConfig {
+            device_name: noise.device_id.clone(),
+            ..session()
+        })
+        .unwrap();
+    assert_eq!(stream.device_id.to_string(), noise.device_id);
+    assert_eq!(stream.sample_rate, 44_100);
+
+    faults.unplug("Synthetic Silence");
+    assert_eq!(recorder.enumerate_recording_devices().unwrap().len(), 2);
+}
+
+#[test]
+fn init_rejects_unknown_devices_and_a_second_session() {
+    let recorder = recorder();
+    let unknown = UserRecordingSessionConfig {
+        device_name: "No Such Mic".to_string(),
+        ..session()
+    };
+    assert!(recorder.init_recording_session(unknown).is_err());
+
+    recorder.init_recording_session(session()).unwrap();
+    assert!(recorder.init_recording_session(session()).is_err());
+}
+
+#[test]
+fn init_negotiates_among_the_supported_configs() {
+    let device = SyntheticDevice {
+        supported_configs: vec![
+            cpal::SupportedStreamConfigRange::new(
+                2,
+                cpal::SampleRate(8_000),
+                cpal::SampleRate(48_000),
+                cpal::SupportedBufferSize::Range { min: 64, max: 4096 },
+                cpal::SampleFormat::I16,
+            ),
+            cpal::SupportedStreamConfigRange::new(
+                1,
+                cpal::SampleRate(16_000),
+                cpal::SampleRate(16_000),
+                cpal::SupportedBufferSize::Range { min: 64, max: 4096 },
+                cpal::SampleFormat::I32,
+            ),
+        ],
+        ..SyntheticDevice::new(
+            "default",
+            Signal::Sine {
+                frequency: 440.0,
+                amplitude: 0.5,
+            },
+        )
+    };
+    let negotiate = |stream: StreamRequest| {
+        let backend = SyntheticBackend::new(vec![device.clone()]);
+        let recorder = Recorder::with_backend(Box::new(backend)).unwrap();
+        let negotiated = recorder
+            .init_recording_session(UserRecordingSessionConfig {
+                stream,
+                ..session()
+            })
+            .unwrap();
+        recorder.start_recording_in_memory().unwrap();
+        wait_for_frames(&recorder, 1600);
+        let recording = recorder.stop_recording().unwrap();
+        (negotiated, wav_reader(&recording.data).spec())
+    };
+
+    let (stream, spec) = negotiate(StreamRequest {
+        sample_rate: Some(22_050),
+        channels: Some(2),
+        sample_format: Some(cpal::SampleFormat::I16),
+        buffer_size: cpal::BufferSize::Fixed(16),
+    });
+    assert_eq!(
+        (stream.channels, stream.sample_rate, stream.sample_format),
+        (2, 22_050, cpal::SampleFormat::I16)
+    );
+    assert_eq!(stream.buffer_size, cpal::BufferSize::Fixed(64));
+    assert_eq!((spec.channels, spec.sample_rate), (2, 22_050));
+
+    // The exact rate beats the exact sample format.
+    let (stream, spec) = negotiate(StreamRequest {
+        sample_rate: Some(16_000),
+        sample_format: Some(cpal::SampleFormat::F32),
+        channels: Some(1),
+        ..StreamRequest::default()
+    });
+    assert_eq!(
+        (stream.channels, stream.sample_rate, stream.sample_format),
+        (1, 16_000, cpal::SampleFormat::I32)
+    );
+    assert_eq!((spec.channels, spec.sample_rate), (1, 16_000));
+}
+
+#[test]
+fn in_memory_recording_returns_a_wav() {
+    let recorder = recorder();
+    recorder.init_recording_session(session()).unwrap();
+    recorder.start_recording_in_memory().unwrap();
+    wait_for_frames(&recorder, 4800);
+    let recording = recorder.stop_recording().unwrap();
+
+    assert_eq!(recording.mime_type, "audio/wav");
+    assert!(recording.segments.is_empty());
+    let reader = wav_reader(&recording.data);
+    assert_eq!(
+        reader.spec(),
+        hound::WavSpec {
+            channels: 2,
+            sample_rate: 48_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        }
+    );
+    assert!(reader.duration() >= 4800);
+}
+
+#[test]
+fn file_recording_is_returned_and_removed() {
+    let dir = test_dir("file");
+    let recorder = recorder();
+    recorder.init_recording_session(session()).unwrap();
+    recorder
+        .start_recording(dir.join("take").to_string_lossy().into_owned())
+        .unwrap();
+    assert_eq!(files_in(&dir), ["take.wav"]);
+    wait_for_frames(&recorder, 4800);
+    let recording = recorder.stop_recording().unwrap();
+
+    assert!(wav_reader(&recording.data).duration() >= 4800);
+    assert!(files_in(&dir).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn channel_policy_shapes_the_recorded_channels() {
+    let record = |channel_policy| {
+        let recorder = stereo_dc_recorder();
+        recorder
+            .init_recording_session(UserRecordingSessionConfig {
+                channel_policy,
+                ..session()
+            })
+            .unwrap();
+        recorder.start_recording_in_memory().unwrap();
+        wait_for_frames(&recorder, 4800);
+        let recording = recorder.stop_recording().unwrap();
+        let reader = wav_reader(&recording.data);
+        let channels = reader.spec().channels;
+        let samples: Vec<i16> = reader.into_samples().map(Result::unwrap).collect();
+        (channels, samples)
+    };
+
+    let (channels, samples) = record(ChannelPolicy::DownmixMono);
+    assert_eq!(channels, 1);
+    assert!(samples.iter().all(|&s| s == 4096));
+
+    let (channels, samples) = record(ChannelPolicy::Select(vec![1, 0]));
+    assert_eq!(channels, 2);
+    assert!(samples
+        .chunks_exact(2)
+        .all(|frame| frame == [-8192, 16_384]));
+
+    let recorder = stereo_dc_recorder();
+    let out_of_range = UserRecordingSessionConfig {
+        channel_policy: ChannelPolicy::Select(vec![2]),
+        ..session()
+    };
+    assert!(recorder.init_recording_session(out_of_range).is_err());
+}
+
+#[test]
+fn meter_readings_arrive_at_the_configured_rate() {
+    let recorder = recorder();
+    let meter = recorder.subscribe_meter();
+    recorder
+        .init_recording_session(UserRecordingSessionConfig {
+            meter_rate_hz: 20,
+            ..session()
+        })
+        .unwrap();
+
+    // Readings every 50 ms, for the shared stream's 440 Hz sine at half
+    // scale on both channels.
+    let readings_for = |duration: Duration| {
+        meter.try_iter().count();
+        let deadline = Instant::now() + duration;
+        let mut readings = Vec::new();
+        while let Ok(reading) =
+            meter.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            readings.push(reading);
+        }
+        readings
+    };
+    let idle = readings_for(Duration::from_millis(500));
+    assert!((7..=13).contains(&idle.len()), "{}", idle.len());
+    for reading in &idle {
+        assert_eq!(reading.rms_dbfs.len(), 2);
+        assert!((reading.rms_dbfs[0] + 9.03).abs() < 0.2, "{:?}", reading);
+        assert!((reading.peak_dbfs[1] + 6.02).abs() < 0.2, "{:?}", reading);
+    }
+
+    recorder.start_recording_in_memory().unwrap();
+    recorder.pause_recording().unwrap();
+    let paused = readings_for(Duration::from_millis(500));
+    assert!((7..=13).contains(&paused.len()), "{}", paused.len());
+    recorder.stop_recording().unwrap();
+}
+
+#[test]
+fn clipping_and_dead_input_are_reported() {
+    // Half a second pinned at full scale, then silence for good.
+    let device = SyntheticDevice::new("default", Signal::Scripted(vec![1.0; 24_000].into()));
+    let recorder = Recorder::with_backend(Box::new(SyntheticBackend::new(vec![device]))).unwrap();
+    let events = recorder.subscribe();
+    recorder
+        .init_recording_session(UserRecordingSessionConfig {
+            input_health: InputHealthConfig {
+                dead_after: Duration::from_millis(200),
+                ..InputHealthConfig::default()
+            },
+            ..session()
+        })
+        .unwrap();
+    recorder.start_recording_in_memory().unwrap();
+    wait_for_event(&events, |event| {
+        matches!(event, RecorderEvent::InputWarning(InputWarning::Clipping))
+    });
+    wait_for_event(&events, |event| {
+        matches!(event, RecorderEvent::InputWarning(InputWarning::DeadInput))
+    });
+    let recording = recorder.stop_recording().unwrap();
+
+    let stats = recording.input_stats;
+    // One uninterrupted run, however much of it was recorded.
+    assert_eq!(stats.clipped_runs, 1);
+    assert!(stats.full_scale_samples >= 3);
+    assert_eq!(stats.dead_stretches, 1);
+    assert!(!events
+        .try_iter()
+        .any(|event| matches!(event, RecorderEvent::InputWarning(InputWarning::Clipping))));
+}
+
+#[test]
+fn sink_recording_streams_into_the_callers_sink() {
+    let recorder = recorder();
+    recorder.init_recording_session(session()).unwrap();
+    let sink = SharedSink::default();
+    recorder
+        .start_recording_to_sink(Box::new(sink.clone()))
+        .unwrap();
+    assert_eq!(
+        recorder.get_state().unwrap().output,
+        Some(RecordingOutput::Sink)
+    );
+    wait_for_frames(&recorder, 4800);
+    let recording = recorder.stop_recording().unwrap();
+
+    assert!(recording.data.is_empty());
+    assert_eq!(recording.mime_type, "audio/wav");
+    assert!(wav_reader(&sink.bytes()).duration() >= 4800);
+
+    let sink = SharedSink::default();
+    recorder
+        .start_recording_with(
+            RecordingTarget::Sink(Box::new(sink.clone())),
+            RecordingOptions {
+                format: OutputFormat::Flac,
+                ..RecordingOptions::default()
+            },
+        )
+        .unwrap();
+    wait_for_frames(&recorder, 4800);
+    let recording = recorder.stop_recording().unwrap();
+    assert_eq!(recording.mime_type, "audio/flac");
+    let mut reader = claxon::FlacReader::new(Cursor::new(sink.bytes())).unwrap();
+    let frames = reader.streaminfo().samples.unwrap();
+    assert!(frames >= 4800);
+    assert_eq!(reader.samples().count() as u64, frames * 2);
+
+    // Segmenting needs files.
+    assert!(recorder
+        .start_recording_with(
+            RecordingTarget::Sink(Box::new(SharedSink::default())),
+            RecordingOptions {
+                segment: Some(SegmentLimit::Duration(Duration::from_millis(100))),
+                ..RecordingOptions::default()
+            },
+        )
+        .is_err());
+}
+
+#[test]
+fn armed_file_recording_can_be_cancelled_before_it_triggers() {
+    let dir = test_dir("arm-cancel");
+    let backend = SyntheticBackend::default();
+    let recorder = Recorder::with_backend(Box::new(backend)).unwrap();
+    recorder
+        .init_recording_session(UserRecordingSessionConfig {
+            device_name: "Synthetic Silence".to_string(),
+            ..session()
+        })
+        .unwrap();
+    let path = dir.join("take.wav").to_string_lossy().into_owned();
+    recorder
+        .arm_recording(
+            RecordingTarget::File(path.clone()),
+            RecordingOptions::default(),
+        )
+        .unwrap();
+    let status = recorder.get_state().unwrap();
+    assert!(matches!(status.state, RecordingState::Armed));
+    assert_eq!(status.output, Some(RecordingOutput::File(path)));
+    assert_eq!(files_in(&dir), ["take.wav"]);
+
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(recorder.get_state().unwrap().recorded_frames, 0);
+    recorder.cancel_recording().unwrap();
+    assert!(files_in(&dir).is_empty());
+    assert!(matches!(
+        recorder.get_state().unwrap().state,
+        RecordingState::Initialized
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "opus")]
+#[test]
+fn opus_recording_returns_an_ogg_stream() {
+    use whispering_rust_audio_recorder_test_start_stop_with_shared_stream::opus::OpusConfig;
+
+    let dir = test_dir("opus");
+    let recorder = recorder();
+    recorder
+        .init_recording_session(UserRecordingSessionConfig {
+            target_sample_rate: Some(16_000),
+            ..session()
+        })
+        .unwrap();
+    recorder
+        .start_recording_as(
+            dir.join("take").to_string_lossy().into_owned(),
+            OutputFormat::Opus(OpusConfig::default()),
+        )
+        .unwrap();
+    assert_eq!(files_in(&dir), ["take.opus"]);
+    assert_eq!(
+        recorder.get_state().unwrap().spec.unwrap().sample_rate,
+        48_000
+    );
+    wait_for_frames(&recorder, 4800);
+    let recording = recorder.stop_recording().unwrap();
+
+    assert_eq!(recording.mime_type, "audio/ogg");
+    assert!(recording.data.starts_with(b"OggS"));
+    let head = recording
+        .data
+        .windows(8)
+        .position(|window| window == b"OpusHead")
+        .unwrap();
+    // Channels, then the input rate recorded for players.
+    assert_eq!(recording.data[head + 9], 2);
+    assert_eq!(
+        recording.data[head + 12..head + 16],
+        16_000u32.to_le_bytes()
+    );
+    assert!(files_in(&dir).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn nothing_is_recorded_while_paused() {
+    let recorder = recorder();
+    recorder.init_recording_session(session()).unwrap();
+    recorder.start_recording_in_memory().unwrap();
+    wait_for_frames(&recorder, 480);
+
+    recorder.pause_recording().unwrap();
+    let status = recorder.get_state().unwrap();
+    assert!(matches!(status.state, RecordingState::Paused));
+    assert!(recorder.pause_recording().is_err());
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(
+        recorder.get_state().unwrap().recorded_frames,
+        status.recorded_frames
+    );
+
+    recorder.resume_recording().unwrap();
+    assert!(recorder.resume_recording().is_err());
+    wait_for_frames(&recorder, status.recorded_frames + 480);
+    let recording = recorder.stop_recording().unwrap();
+    assert!(wav_reader(&recording.data).duration() as u64 >= status.recorded_frames + 480);
+}
+
+#[test]
+fn cancel_deletes_the_file() {
+    let dir = test_dir("cancel");
+    let recorder = recorder();
+    recorder.init_recording_session(session()).unwrap();
+    assert!(matches!(
+        recorder.cancel_recording(),
+        Err(RecorderError::NoActiveRecording)
+    ));
+
+    recorder
+        .start_recording(dir.join("take").to_string_lossy().into_owned())
+        .unwrap();
+    wait_for_frames(&recorder, 480);
+    recorder.cancel_recording().unwrap();
+
+    assert!(files_in(&dir).is_empty());
+    assert!(matches!(
+        recorder.get_state().unwrap().state,
+        RecordingState::Initialized
+    ));
+    assert!(matches!(
+        recorder.stop_recording(),
+        Err(RecorderError::NoActiveRecording)
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn recording_needs_a_session() {
+    let recorder = recorder();
+    assert!(recorder.start_recording_in_memory().is_err());
+    assert!(recorder.pause_recording().is_err());
+    assert!(matches!(
+        recorder.stop_recording(),
+        Err(RecorderError::NoActiveRecording)
+    ));
+}
+
+#[test]
+fn segmented_recording_reports_its_segments() {
+    let dir = test_dir("segments");
+    let recorder = recorder();
+    recorder.init_recording_session(session()).unwrap();
+    recorder
+        .start_recording_with(
+            RecordingTarget::File(dir.join("meeting.wav").to_string_lossy().into_owned()),
+            RecordingOptions {
+                segment: Some(SegmentLimit::Duration(Duration::from_millis(100))),
+                ..RecordingOptions::default()
+            },
+        )
+        .unwrap();
+    wait_for_frames(&recorder, 4800 * 3 + 1);
+    let recording = recorder.stop_recording().unwrap();
+
+    assert!(recording.data.is_empty());
+    let indices: Vec<u32> = recording.segments.iter().map(|s| s.index).collect();
+    assert_eq!(indices, (1..=indices.len() as u32).collect::<Vec<_>>());
+    assert!(indices.len() >= 4);
+    for segment in &recording.segments[..indices.len() - 1] {
+        assert_eq!(segment.frames, 4800);
+        assert_eq!(
+            hound::WavReader::open(&segment.path).unwrap().duration(),
+            4800
+        );
+    }
+    assert_eq!(files_in(&dir).len(), indices.len());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn auto_stop_follows_the_configured_trailing_silence() {
+    let recorder = scripted_recorder(0, 96_000);
+    let events = recorder.subscribe();
+    recorder
+        .init_recording_session(UserRecordingSessionConfig {
+            auto_stop_after: Some(Duration::from_millis(500)),
+            ..session()
+        })
+        .unwrap();
+    recorder.start_recording_in_memory().unwrap();
+    wait_for_event(&events, |event| {
+        matches!(
+            event,
+            RecorderEvent::RecordingAutoStopped(AutoStopReason::Silence)
+        )
+    });
+    assert!(matches!(
+        recorder.get_state().unwrap().state,
+        RecordingState::Initialized
+    ));
+
+    let recording = recorder.stop_recording().unwrap();
+    let samples: Vec<i16> = wav_reader(&recording.data)
+        .into_samples()
+        .map(Result::unwrap)
+        .collect();
+    let trailing = samples.iter().rev().take_while(|&&s| s == 0).count();
+    // The hangover is part of the silence, and the stop lands on the block
+    // that reaches it.
+    assert!((24_000..24_000 + 480).contains(&trailing), "{}", trailing);
+    assert!(trailing < samples.len());
+}
+
+#[test]
+fn start_writes_the_pre_roll_before_live_audio() {
+    // A sawtooth rising by about 1.6 LSB per frame, so any gap or overlap
+    // between the pre-roll and the live samples shows as a jump.
+    let samples: Vec<f32> = (0..240_000)
+        .map(|frame| (frame % 20_000) as f32 / 20_000.0 - 0.5)
+        .collect();
+    let device = SyntheticDevice::new("default", Signal::Scripted(samples.into()));
+    let recorder = Recorder::with_backend(Box::new(SyntheticBackend::new(vec![device]))).unwrap();
+    recorder
+        .init_recording_session(UserRecordingSessionConfig {
+            pre_roll: Duration::from_millis(200),
+            ..session()
+        })
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+
+    recorder.start_recording_in_memory().unwrap();
+    // The buffered 200 ms are written at once.
+    let started_with = recorder.get_state().unwrap().recorded_frames;
+    assert!((9600..14_400).contains(&started_with), "{}", started_with);
+    wait_for_frames(&recorder, 19_200);
+    let recording = recorder.stop_recording().unwrap();
+
+    let samples: Vec<i16> = wav_reader(&recording.data)
+        .into_samples()
+        .map(Result::unwrap)
+        .collect();
+    assert!(samples.len() >= 19_200);
+    for (frame, pair) in samples.windows(2).enumerate() {
+        let step = pair[1] as i32 - pair[0] as i32;
+        assert!(
+            (1..=2).contains(&step) || step < -32_000,
+            "jump of {} after frame {}",
+            step,
+            frame
+        );
+    }
+}
+
+#[test]
+fn auto_stop_waits_for_speech() {
+    let recorder = recorder();
+    let events = recorder.subscribe();
+    recorder
+        .init_recording_session(UserRecordingSessionConfig {
+            device_name: "Synthetic Silence".to_string(),
+            auto_stop_after: Some(Duration::from_millis(100)),
+            vad: VadConfig {
+                hangover: Duration::from_millis(50),
+                ..VadConfig::default()
+            },
+            ..session()
+        })
+        .unwrap();
+    recorder.start_recording_in_memory().unwrap();
+    wait_for_frames(&recorder, 48_000 / 2);
+
+    assert!(matches!(
+        recorder.get_state().unwrap().state,
+        RecordingState::Recording
+    ));
+    assert!(!events
+        .try_iter()
+        .any(|event| matches!(event, RecorderEvent::RecordingAutoStopped(_))));
+    recorder.stop_recording().unwrap();
+}
+
+#[test]
+fn armed_recording_starts_at_speech_with_its_pre_roll() {
+    let recorder = scripted_recorder(96_000, 96_000);
+    let events = recorder.subscribe();
+    recorder
+        .init_recording_session(UserRecordingSessionConfig {
+            pre_roll: Duration::from_millis(100),
+            ..session()
+        })
+        .unwrap();
+    recorder
+        .arm_recording(RecordingTarget::Memory, RecordingOptions::default())
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    let status = recorder.get_state().unwrap();
+    assert!(matches!(status.state, RecordingState::Armed));
+    assert_eq!(status.recorded_frames, 0);
+    assert!(recorder.pause_recording().is_err());
+
+    wait_for_event(&events, |event| {
+        matches!(event, RecorderEvent::RecordingTriggered)
+    });
+    assert!(matches!(
+        recorder.get_state().unwrap().state,
+        RecordingState::Recording
+    ));
+    wait_for_frames(&recorder, 4800 + 4800);
+    let recording = recorder.stop_recording().unwrap();
+
+    let samples: Vec<i16> = wav_reader(&recording.data)
+        .into_samples()
+        .map(Result::unwrap)
+        .collect();
+    // 100 ms of pre-roll silence, then the tone, which starts at a zero
+    // crossing.
+    let first_sound = samples.iter().position(|&s| s != 0).unwrap();
+    assert!((4800..4802).contains(&first_sound), "{}", first_sound);
+}
+
+#[test]
+fn max_duration_is_frame_accurate() {
+    let limit = Duration::from_millis(250);
+    let recording = record_until_limit(session(), OutputFormat::Wav, limit);
+    assert_eq!(wav_reader(&recording.data).duration(), 12_000);
+
+    for quality in [ResampleQuality::Fast, ResampleQuality::High] {
+        let resampled = UserRecordingSessionConfig {
+            target_sample_rate: Some(16_000),
+            resample_quality: quality,
+            ..session()
+        };
+        let recording = record_until_limit(resampled, OutputFormat::Wav, limit);
+        let reader = wav_reader(&recording.data);
+        assert_eq!(reader.spec().sample_rate, 16_000);
+        assert_eq!(reader.duration(), 4000, "{:?}", quality);
+    }
+
+    let resampled = UserRecordingSessionConfig {
+        target_sample_rate: Some(16_000),
+        ..session()
+    };
+    let recording = record_until_limit(resampled, OutputFormat::Flac, limit);
+    assert_eq!(recording.mime_type, "audio/flac");
+    let mut reader = claxon::FlacReader::new(Cursor::new(recording.data)).unwrap();
+    assert_eq!(reader.streaminfo().samples, Some(4000));
+    assert_eq!(reader.samples().count(), 4000 * 2);
+}
+
+#[test]
+fn auto_stopped_recording_must_be_collected_before_the_next() {
+    let dir = test_dir("uncollected");
+    let recorder = recorder();
+    let events = recorder.subscribe();
+    recorder.init_recording_session(session()).unwrap();
+    let file = |name: &str| RecordingTarget::File(dir.join(name).to_string_lossy().into_owned());
+    recorder
+        .start_recording_with(
+            file("a.wav"),
+            RecordingOptions {
+                max_duration: Some(Duration::from_millis(50)),
+                ..RecordingOptions::default()
+            },
+        )
+        .unwrap();
+    wait_for_event(&events, |event| {
+        matches!(
+            event,
+            RecorderEvent::RecordingAutoStopped(AutoStopReason::MaxDuration)
+        )
+    });
+
+    assert!(recorder
+        .start_recording_with(file("b.wav"), RecordingOptions::default())
+        .is_err());
+    assert!(recorder
+        .arm_recording(file("b.wav"), RecordingOptions::default())
+        .is_err());
+    assert_eq!(files_in(&dir), ["a.wav"]);
+
+    let recording = recorder.stop_recording().unwrap();
+    assert_eq!(wav_reader(&recording.data).duration(), 2400);
+    assert!(files_in(&dir).is_empty());
+    recorder
+        .start_recording_with(file("b.wav"), RecordingOptions::default())
+        .unwrap();
+    recorder.cancel_recording().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn start_is_refused_while_a_recording_is_active() {
+    let dir = test_dir("busy");
+    let recorder = recorder();
+    recorder.init_recording_session(session()).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    recorder
+        .start_recording_as(path("a"), OutputFormat::Flac)
+        .unwrap();
+    wait_for_frames(&recorder, 4800);
+
+    assert!(recorder
+        .start_recording_as(path("b"), OutputFormat::Flac)
+        .is_err());
+    assert!(recorder
+        .arm_recording(
+            RecordingTarget::File(path("b.flac")),
+            RecordingOptions::default()
+        )
+        .is_err());
+    recorder.pause_recording().unwrap();
+    assert!(recorder.start_recording_in_memory().is_err());
+    assert_eq!(files_in(&dir), ["a.flac"]);
+
+    let recording = recorder.stop_recording().unwrap();
+    let mut reader = claxon::FlacReader::new(Cursor::new(recording.data)).unwrap();
+    let frames = reader.streaminfo().samples.unwrap();
+    assert!(frames >= 4800);
+    assert_eq!(reader.samples().count() as u64, frames * 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn closing_the_session_finalizes_the_active_recording() {
+    let dir = test_dir("close");
+    let recorder = recorder();
+    recorder.init_recording_session(session()).unwrap();
+    let path = dir.join("take").to_string_lossy().into_owned();
+    recorder
+        .start_recording_as(path.clone(), OutputFormat::Flac)
+        .unwrap();
+    wait_for_frames(&recorder, 4800);
+    recorder.close_recording_session().unwrap();
+
+    let mut reader = claxon::FlacReader::open(format!("{}.flac", path)).unwrap();
+    let frames = reader.streaminfo().samples.unwrap();
+    assert!(frames >= 4800);
+    assert_eq!(reader.samples().count() as u64, frames * 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn chunks_have_a_fixed_length_and_contiguous_sequence() {
+    let recorder = recorder();
+    let chunks = recorder.subscribe_chunks(ChunkConfig::default());
+    recorder.init_recording_session(session()).unwrap();
+    for sequence in 0..20 {
+        let chunk = chunks.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(chunk.sequence, sequence);
+        // 30 ms of mono at 16 kHz.
+        assert_eq!(chunk.samples.len(), 480);
+    }
+    let chunk = chunks.recv_timeout(TIMEOUT).unwrap();
+    let peak = chunk
+        .samples
+        .iter()
+        .fold(0.0f32, |peak, s| peak.max(s.abs()));
+    assert!((0.45..0.55).contains(&peak), "{}", peak);
+}
+
+#[test]
+fn chunks_flow_while_idle_and_paused() {
+    let recorder = recorder();
+    let chunks = recorder.subscribe_chunks(ChunkConfig::default());
+    recorder.init_recording_session(session()).unwrap();
+    // Idle: the stream runs without a recording.
+    let idle = chunks.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(idle.sequence, 0);
+
+    recorder.start_recording_in_memory().unwrap();
+    recorder.pause_recording().unwrap();
+    let frames = recorder.get_state().unwrap().recorded_frames;
+    let mut last = idle.sequence;
+    for chunk in chunks.try_iter() {
+        last = chunk.sequence;
+    }
+    // Paused: nothing is written, but chunks keep coming without a gap.
+    for _ in 0..10 {
+        let chunk = chunks.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(chunk.sequence, last + 1);
+        assert_eq!(chunk.samples.len(), 480);
+        last = chunk.sequence;
+    }
+    assert_eq!(recorder.get_state().unwrap().recorded_frames, frames);
+    recorder.stop_recording().unwrap();
+}
+
+#[test]
+fn slow_chunk_consumers_see_a_gap() {
+    let recorder = recorder();
+    let chunks = recorder.subscribe_chunks(ChunkConfig {
+        capacity: 2,
+        ..ChunkConfig::default()
+    });
+    recorder.init_recording_session(session()).unwrap();
+    recorder.start_recording_in_memory().unwrap();
+    wait_for_frames(&recorder, 4800 * 3);
+
+    let queued: Vec<u64> = chunks.try_iter().map(|chunk| chunk.sequence).collect();
+    assert_eq!(queued, [0, 1]);
+    let next = chunks.recv_timeout(TIMEOUT).unwrap();
+    assert!(next.sequence > 2, "{}", next.sequence);
+    assert_eq!(next.samples.len(), 480);
+    assert_eq!(
+        chunks.recv_timeout(TIMEOUT).unwrap().sequence,
+        next.sequence + 1
+    );
+
+    // The recording itself is unaffected.
+    let recording = recorder.stop_recording().unwrap();
+    assert!(wav_reader(&recording.data).duration() >= 4800 * 3);
+}
+
+#[test]
+fn device_loss_finalizes_the_recording() {
+    let backend = SyntheticBackend::default();
+    let faults = backend.faults();
+    let recorder = Recorder::with_backend(Box::new(backend)).unwrap();
+    let events = recorder.subscribe();
+    recorder.init_recording_session(session()).unwrap();
+    recorder.start_recording_in_memory().unwrap();
+    wait_for_frames(&recorder, 4800);
+
+    faults.unplug("default");
+    wait_for_event(&events, |event| {
+        matches!(
+            event,
+            RecorderEvent::RecordingAutoStopped(AutoStopReason::DeviceLost)
+        )
+    });
+    assert!(matches!(
+        recorder.get_state().unwrap().state,
+        RecordingState::Error(_)
+    ));
+    assert!(recorder.start_recording_in_memory().is_err());
+
+    let recording = recorder.stop_recording().unwrap();
+    assert!(wav_reader(&recording.data).duration() >= 4800);
+}
+
+#[test]
+fn repeated_backend_errors_lose_the_device_once() {
+    let backend = SyntheticBackend::default();
+    let faults = backend.faults();
+    let recorder = Recorder::with_backend(Box::new(backend)).unwrap();
+    let events = recorder.subscribe();
+    recorder.init_recording_session(session()).unwrap();
+
+    for _ in 0..3 {
+        faults.stream_error(
+            "default",
+            "snd_pcm_poll_descriptors failed: ENODEV: No such device",
+        );
+    }
+    wait_for_event(&events, |event| matches!(event, RecorderEvent::DeviceLost));
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!events
+        .try_iter()
+        .any(|event| matches!(event, RecorderEvent::DeviceLost)));
+    assert!(matches!(
+        recorder.get_state().unwrap().state,
+        RecordingState::Error(_)
+    ));
+}
+
+#[test]
+fn reconnect_keeps_a_plain_recording_collectable() {
+    let dir = test_dir("reconnect-file");
+    let backend = SyntheticBackend::default();
+    let faults = backend.faults();
+    let recorder = Recorder::with_backend(Box::new(backend)).unwrap();
+    let events = recorder.subscribe();
+    recorder
+        .init_recording_session(reconnecting_session())
+        .unwrap();
+    recorder
+        .start_recording(dir.join("take").to_string_lossy().into_owned())
+        .unwrap();
+    wait_for_frames(&recorder, 4800);
+
+    faults.unplug("default");
+    wait_for_event(&events, |event| matches!(event, RecorderEvent::DeviceLost));
+    faults.replug("default");
+    wait_for_event(&events, |event| {
+        matches!(
+            event,
+            RecorderEvent::Reconnected {
+                continued_in: None,
+                ..
+            }
+        )
+    });
+
+    let recording = recorder.stop_recording().unwrap();
+    assert!(wav_reader(&recording.data).duration() >= 4800);
+    assert!(files_in(&dir).is_empty());
+
+    // The reopened stream records as usual.
+    recorder.start_recording_in_memory().unwrap();
+    wait_for_frames(&recorder, 480);
+    recorder.stop_recording().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reconnect_continues_a_segmented_recording() {
+    let dir = test_dir("reconnect-segments");
+    let backend = SyntheticBackend::default();
+    let faults = backend.faults();
+    let recorder = Recorder::with_backend(Box::new(backend)).unwrap();
+    let events = recorder.subscribe();
+    recorder
+        .init_recording_session(reconnecting_session())
+        .unwrap();
+    recorder
+        .start_recording_with(
+            RecordingTarget::File(dir.join("meeting.wav").to_string_lossy().into_owned()),
+            RecordingOptions {
+                segment: Some(SegmentLimit::Duration(Duration::from_millis(100))),
+                ..RecordingOptions::default()
+            },
+        )
+        .unwrap();
+    wait_for_frames(&recorder, 4800 + 1);
+
+    faults.unplug("default");
+    wait_for_event(&events, |event| matches!(event, RecorderEvent::DeviceLost));
+    // Polling must not hold off the reconnect.
+    let deadline = Instant::now() + Duration::from_millis(100);
+    while Instant::now() < deadline {
+        recorder.get_state().unwrap();
+    }
+    faults.replug("default");
+    wait_for_event(&events, |event| {
+        matches!(
+            event,
+            RecorderEvent::Reconnected {
+                continued_in: Some(_),
+                ..
+            }
+        )
+    });
+    wait_for_frames(&recorder, 480);
+    let recording = recorder.stop_recording().unwrap();
+
+    let indices: Vec<u32> = recording.segments.iter().map(|s| s.index).collect();
+    assert_eq!(indices, (1..=indices.len() as u32).collect::<Vec<_>>());
+    assert!(indices.len() >= 3);
+    let paths: Vec<String> = recording
+        .segments
+        .iter()
+        .map(|segment| {
+            PathBuf::from(&segment.path)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    assert_eq!(files_in(&dir), paths);
+    std::fs::remove_dir_all(&dir).unwrap();
+}